@@ -0,0 +1,4 @@
+// Recompile when a migration is added, as `sqlx::migrate!` embeds the migrations directory.
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+}
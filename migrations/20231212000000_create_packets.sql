@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS packets (
+    name TEXT PRIMARY KEY,
+    saved_at TIMESTAMPTZ NOT NULL,
+    expires_at TIMESTAMPTZ
+);
@@ -0,0 +1,22 @@
+-- Both tables used to be (re)created by the day 13 and day 18 reset endpoints, so they may
+-- already exist on deployed databases.
+CREATE TABLE IF NOT EXISTS regions (
+    id INT PRIMARY KEY,
+    name VARCHAR(50)
+);
+
+CREATE TABLE IF NOT EXISTS orders (
+    id INT PRIMARY KEY,
+    region_id INT,
+    gift_name VARCHAR(50),
+    quantity INT
+);
+
+-- Orders inserted through day 13 never had a region; give them a nameless one so the foreign
+-- key can be validated.
+INSERT INTO regions (id)
+SELECT DISTINCT region_id FROM orders WHERE region_id IS NOT NULL
+ON CONFLICT DO NOTHING;
+
+ALTER TABLE orders
+    ADD CONSTRAINT orders_region_id_fkey FOREIGN KEY (region_id) REFERENCES regions (id);
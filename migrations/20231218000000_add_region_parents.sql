@@ -0,0 +1,5 @@
+-- Regions form a hierarchy such as country > state > city; top-level regions have no parent.
+ALTER TABLE regions
+    ADD COLUMN IF NOT EXISTS parent_id INT REFERENCES regions (id);
+
+CREATE INDEX IF NOT EXISTS regions_parent_id_idx ON regions (parent_id);
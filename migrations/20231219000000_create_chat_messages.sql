@@ -0,0 +1,9 @@
+-- Only used when the chat history is configured to be kept in Postgres (CHAT_HISTORY_STORE=postgres).
+CREATE TABLE IF NOT EXISTS chat_messages (
+    seq BIGSERIAL PRIMARY KEY,
+    room_id BIGINT NOT NULL,
+    user_name TEXT NOT NULL,
+    message TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS chat_messages_room_id_seq_idx ON chat_messages (room_id, seq);
@@ -0,0 +1,3 @@
+ALTER TABLE chat_messages
+    ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN IF NOT EXISTS edited_at TIMESTAMPTZ;
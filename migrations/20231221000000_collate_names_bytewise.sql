@@ -0,0 +1,6 @@
+-- Names are listed, ranked and paginated in byte order whatever the locale of the database.
+ALTER TABLE regions
+    ALTER COLUMN name TYPE VARCHAR(50) COLLATE "C";
+
+ALTER TABLE orders
+    ALTER COLUMN gift_name TYPE VARCHAR(50) COLLATE "C";
@@ -0,0 +1,16 @@
+-- Orders written before every import was validated may lack a region or a quantity. A missing
+-- quantity never counted towards any total, and orders without a region are moved to a new
+-- nameless placeholder region, which the region reports leave out.
+UPDATE orders SET quantity = 0 WHERE quantity IS NULL;
+
+WITH placeholder AS (
+    INSERT INTO regions (id)
+    SELECT COALESCE((SELECT MIN(id) FROM regions), 1) - 1
+    WHERE EXISTS (SELECT FROM orders WHERE region_id IS NULL)
+    RETURNING id
+)
+UPDATE orders SET region_id = placeholder.id FROM placeholder WHERE orders.region_id IS NULL;
+
+ALTER TABLE orders
+    ALTER COLUMN region_id SET NOT NULL,
+    ALTER COLUMN quantity SET NOT NULL;
@@ -1,45 +1,140 @@
-use actix_web::{get, web, HttpResponse, Responder};
-use serde_json::Value;
+use actix_web::{get, web, HttpResponse};
+use serde::{Deserialize, Serialize};
+
+use crate::error::AppError;
+
+use self::pokemon::PokemonSource;
+
+pub mod pokemon;
 
 const G: f64 = 9.825;
 const HEIGHT: f64 = 10.0;
 
+const GRAVITY_PRESETS: [(&str, f64); 4] = [
+    ("earth", G),
+    ("moon", 1.62),
+    ("mars", 3.721),
+    ("jupiter", 24.79),
+];
+
+/// Expects a [`PokemonSource`] to be registered as app data; it is built once in `main` so that
+/// all workers share its cache.
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(part_1);
     cfg.service(part_2);
 }
 
-async fn get_weight(id: u32) -> f64 {
-    let body = reqwest::get(format!("https://pokeapi.co/api/v2/pokemon/{id}"))
-        .await
-        .unwrap()
-        .text()
-        .await
-        .unwrap();
-
-    let json: Value = serde_json::from_str(body.as_str()).unwrap();
-    json["weight"].as_f64().unwrap()
+async fn get_weight(source: &dyn PokemonSource, id: u32) -> Result<f64, AppError> {
+    Ok(source.get(id).await?.weight)
 }
 
 #[get("/8/weight/{pokedex_number}")]
-async fn part_1(pokedex_number: web::Path<u32>) -> impl Responder {
-    HttpResponse::Ok().body(format!("{}", get_weight(pokedex_number.into_inner()).await / 10.0))
+async fn part_1(
+    pokedex_number: web::Path<u32>,
+    source: web::Data<dyn PokemonSource>,
+) -> Result<HttpResponse, AppError> {
+    let weight = get_weight(source.as_ref(), pokedex_number.into_inner()).await?;
+    Ok(HttpResponse::Ok().body(format!("{}", weight / 10.0)))
+}
+
+#[derive(Deserialize)]
+struct DropParams {
+    gravity: Option<String>,
+    height: Option<f64>,
+    drag: Option<f64>,
+    format: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct DropResponse {
+    velocity: f64,
+    momentum: f64,
+    kinetic_energy: f64,
+    fall_time: f64,
+}
+
+fn parse_gravity(gravity: &str) -> Result<f64, AppError> {
+    let preset = GRAVITY_PRESETS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(gravity))
+        .map(|&(_, g)| g);
+
+    match preset {
+        Some(g) => Ok(g),
+        None => gravity
+            .parse::<f64>()
+            .ok()
+            .filter(|g| g.is_finite() && *g > 0.0)
+            .ok_or_else(|| AppError::BadRequest(format!("invalid gravity: {}", gravity))),
+    }
+}
+
+// With a quadratic drag force `drag * v²` the object approaches its terminal velocity
+// `sqrt(m * g / drag)`; both the impact velocity and the fall time have a closed form.
+fn simulate_drop(weight: f64, gravity: f64, height: f64, drag: f64) -> DropResponse {
+    let mass = weight / 10.0;
+
+    let (velocity, fall_time) = if drag > 0.0 {
+        let terminal = (mass * gravity / drag).sqrt();
+        let velocity = terminal * (1.0 - (-2.0 * gravity * height / terminal.powi(2)).exp()).sqrt();
+        let fall_time = terminal / gravity * (gravity * height / terminal.powi(2)).exp().acosh();
+        (velocity, fall_time)
+    } else {
+        ((2.0 * gravity * height).sqrt(), (2.0 * height / gravity).sqrt())
+    };
+
+    DropResponse {
+        velocity,
+        momentum: velocity * weight / 10.0,
+        kinetic_energy: 0.5 * mass * velocity.powi(2),
+        fall_time,
+    }
 }
 
 #[get("/8/drop/{pokedex_number}")]
-async fn part_2(pokedex_number: web::Path<u32>) -> impl Responder {
-    let weight = get_weight(pokedex_number.into_inner()).await;
-    let momentum = (2.0 * G * HEIGHT).sqrt() * weight / 10.0;
-    HttpResponse::Ok().body(format!("{}", momentum))
+async fn part_2(
+    pokedex_number: web::Path<u32>,
+    params: web::Query<DropParams>,
+    source: web::Data<dyn PokemonSource>,
+) -> Result<HttpResponse, AppError> {
+    let gravity = params.gravity.as_deref().map(parse_gravity).transpose()?.unwrap_or(G);
+    let height = params.height.unwrap_or(HEIGHT);
+    let drag = params.drag.unwrap_or(0.0);
+
+    if !height.is_finite() || height < 0.0 {
+        return Err(AppError::BadRequest(format!("invalid height: {}", height)));
+    }
+    if !drag.is_finite() || drag < 0.0 {
+        return Err(AppError::BadRequest(format!("invalid drag coefficient: {}", drag)));
+    }
+
+    let weight = get_weight(source.as_ref(), pokedex_number.into_inner()).await?;
+    let drop = simulate_drop(weight, gravity, height, drag);
+
+    match params.format.as_deref() {
+        None | Some("plain") => Ok(HttpResponse::Ok().body(format!("{}", drop.momentum))),
+        Some("json") => Ok(HttpResponse::Ok().json(drop)),
+        Some(format) => Err(AppError::BadRequest(format!("unknown format: {}", format))),
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use actix_web::{body, test, App};
+    use std::sync::Arc;
+
+    use actix_web::{body, test, web, App};
+
+    use super::pokemon::{FixturePokemonSource, PokemonSource};
+
+    fn configure(cfg: &mut web::ServiceConfig) {
+        let source: Arc<dyn PokemonSource> = Arc::new(FixturePokemonSource::from_file("assets/pokemon.json").unwrap());
+        cfg.app_data(web::Data::from(source));
+        super::configure(cfg);
+    }
 
     #[actix_web::test]
     async fn test_part_1() {
-        let app = test::init_service(App::new().configure(super::configure)).await;
+        let app = test::init_service(App::new().configure(configure)).await;
 
         let req = test::TestRequest::get().uri("/8/weight/25").to_request();
 
@@ -54,7 +149,7 @@ mod tests {
 
     #[actix_web::test]
     async fn test_part_2() {
-        let app = test::init_service(App::new().configure(super::configure)).await;
+        let app = test::init_service(App::new().configure(configure)).await;
 
         let req = test::TestRequest::get().uri("/8/drop/25").to_request();
 
@@ -66,4 +161,64 @@ mod tests {
         let bytes = body::to_bytes(body).await.unwrap();
         assert_eq!(bytes, "84.10707461325713");
     }
+
+    #[actix_web::test]
+    async fn test_unknown_pokemon() {
+        let app = test::init_service(App::new().configure(configure)).await;
+
+        let req = test::TestRequest::get().uri("/8/weight/9999").to_request();
+
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_part_2_json() {
+        let app = test::init_service(App::new().configure(configure)).await;
+
+        let req = test::TestRequest::get().uri("/8/drop/25?gravity=moon&height=5&format=json").to_request();
+
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+
+        let body = resp.into_body();
+        let bytes = body::to_bytes(body).await.unwrap();
+        let response: super::DropResponse = serde_json::from_slice(&bytes).unwrap();
+
+        assert!((response.velocity - 4.024922359499622).abs() < 1e-9);
+        assert!((response.momentum - 24.149534156997735).abs() < 1e-9);
+        assert!((response.kinetic_energy - 48.6).abs() < 1e-9);
+        assert!((response.fall_time - 2.484519974999766).abs() < 1e-9);
+    }
+
+    #[actix_web::test]
+    async fn test_part_2_drag() {
+        let app = test::init_service(App::new().configure(configure)).await;
+
+        let req = test::TestRequest::get().uri("/8/drop/25?drag=0.5&format=json").to_request();
+
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+
+        let body = resp.into_body();
+        let bytes = body::to_bytes(body).await.unwrap();
+        let response: super::DropResponse = serde_json::from_slice(&bytes).unwrap();
+
+        assert!(response.velocity < (2.0f64 * super::G * super::HEIGHT).sqrt());
+        assert!(response.fall_time > (2.0f64 * super::HEIGHT / super::G).sqrt());
+    }
+
+    #[actix_web::test]
+    async fn test_part_2_invalid_gravity() {
+        let app = test::init_service(App::new().configure(configure)).await;
+
+        let req = test::TestRequest::get().uri("/8/drop/25?gravity=pluto").to_request();
+
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
 }
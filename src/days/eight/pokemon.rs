@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+use crate::error::AppError;
+
+const DEFAULT_BASE_URL: &str = "https://pokeapi.co/api/v2";
+const DEFAULT_CACHE_TTL: u64 = 60 * 60;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Pokemon {
+    pub id: u32,
+    /// Weight in hectograms, as reported by the PokéAPI.
+    pub weight: f64,
+}
+
+#[async_trait]
+pub trait PokemonSource: Send + Sync {
+    async fn get(&self, id: u32) -> Result<Pokemon, AppError>;
+}
+
+/// Builds the source from the environment: a fixture file when `POKEMON_FIXTURES` is set,
+/// otherwise the PokéAPI at `POKEAPI_URL` behind a cache of `POKEAPI_CACHE_TTL` seconds. Fails
+/// when the fixtures cannot be loaded or the TTL is not a number of seconds.
+pub fn from_env() -> Result<Arc<dyn PokemonSource>, AppError> {
+    dotenv::dotenv().ok();
+
+    if let Ok(path) = std::env::var("POKEMON_FIXTURES") {
+        let fixtures = FixturePokemonSource::from_file(&path)
+            .map_err(|err| AppError::Internal(format!("could not load Pokémon fixtures from {}: {}", path, err)))?;
+        return Ok(Arc::new(fixtures));
+    }
+
+    let base_url = std::env::var("POKEAPI_URL").unwrap_or_else(|_| DEFAULT_BASE_URL.to_string());
+    let ttl = match std::env::var("POKEAPI_CACHE_TTL") {
+        Ok(ttl) => ttl.parse().map_err(|_| AppError::Internal(format!("invalid POKEAPI_CACHE_TTL: {}", ttl)))?,
+        Err(_) => DEFAULT_CACHE_TTL,
+    };
+
+    Ok(Arc::new(CachedPokemonSource::new(
+        HttpPokemonSource::new(base_url),
+        Duration::from_secs(ttl),
+    )))
+}
+
+pub struct HttpPokemonSource {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl HttpPokemonSource {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        HttpPokemonSource {
+            client: reqwest::Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl PokemonSource for HttpPokemonSource {
+    async fn get(&self, id: u32) -> Result<Pokemon, AppError> {
+        let response = self.client
+            .get(format!("{}/pokemon/{}", self.base_url, id))
+            .send()
+            .await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(AppError::NotFound(format!("unknown Pokédex number: {}", id)));
+        }
+
+        let body = response.error_for_status()?.text().await?;
+        serde_json::from_str(&body).map_err(AppError::upstream)
+    }
+}
+
+pub struct CachedPokemonSource<S> {
+    inner: S,
+    ttl: Duration,
+    entries: Mutex<HashMap<u32, (Instant, Pokemon)>>,
+}
+
+impl<S: PokemonSource> CachedPokemonSource<S> {
+    pub fn new(inner: S, ttl: Duration) -> Self {
+        CachedPokemonSource {
+            inner,
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl<S: PokemonSource> PokemonSource for CachedPokemonSource<S> {
+    async fn get(&self, id: u32) -> Result<Pokemon, AppError> {
+        if let Some((fetched, pokemon)) = self.entries.lock().await.get(&id) {
+            if fetched.elapsed() < self.ttl {
+                return Ok(pokemon.clone());
+            }
+        }
+
+        let pokemon = self.inner.get(id).await?;
+        self.entries.lock().await.insert(id, (Instant::now(), pokemon.clone()));
+        Ok(pokemon)
+    }
+}
+
+pub struct FixturePokemonSource {
+    pokemon: HashMap<u32, Pokemon>,
+}
+
+impl FixturePokemonSource {
+    pub fn new(pokemon: Vec<Pokemon>) -> Self {
+        FixturePokemonSource {
+            pokemon: pokemon.into_iter().map(|p| (p.id, p)).collect(),
+        }
+    }
+
+    /// Loads a JSON array of `{"id", "weight"}` objects; other fields are ignored.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, AppError> {
+        let contents = std::fs::read_to_string(path).map_err(AppError::internal)?;
+        let pokemon: Vec<Pokemon> = serde_json::from_str(&contents).map_err(AppError::internal)?;
+        Ok(FixturePokemonSource::new(pokemon))
+    }
+}
+
+#[async_trait]
+impl PokemonSource for FixturePokemonSource {
+    async fn get(&self, id: u32) -> Result<Pokemon, AppError> {
+        self.pokemon
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| AppError::NotFound(format!("unknown Pokédex number: {}", id)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+
+    use crate::error::AppError;
+
+    use super::{CachedPokemonSource, FixturePokemonSource, Pokemon, PokemonSource};
+
+    struct CountingSource {
+        inner: FixturePokemonSource,
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl PokemonSource for CountingSource {
+        async fn get(&self, id: u32) -> Result<Pokemon, AppError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            self.inner.get(id).await
+        }
+    }
+
+    fn counting_source() -> CountingSource {
+        CountingSource {
+            inner: FixturePokemonSource::new(vec![Pokemon { id: 25, weight: 60.0 }]),
+            calls: AtomicUsize::new(0),
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_cache_hit() {
+        let source = CachedPokemonSource::new(counting_source(), Duration::from_secs(60));
+
+        assert_eq!(source.get(25).await.unwrap().weight, 60.0);
+        assert_eq!(source.get(25).await.unwrap().weight, 60.0);
+        assert_eq!(source.inner.calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[actix_web::test]
+    async fn test_cache_expired() {
+        let source = CachedPokemonSource::new(counting_source(), Duration::ZERO);
+
+        source.get(25).await.unwrap();
+        source.get(25).await.unwrap();
+        assert_eq!(source.inner.calls.load(Ordering::SeqCst), 2);
+    }
+}
@@ -1,5 +1,14 @@
-use actix_web::{get, HttpResponse, post, Responder, web};
-use sqlx::PgPool;
+use std::collections::HashMap;
+
+use actix_web::{get, HttpRequest, HttpResponse, post, web};
+use serde::{Deserialize, Serialize};
+
+use crate::error::AppError;
+use crate::migrations::RequireMigrations;
+use crate::orders::{InsertParams, MissingRegions, Order, OrderRepository, Region, RegionRollup, RegionTopList, TopListQuery};
+use crate::transfer::{self, ExportParams};
+
+pub mod resources;
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(part_1_reset);
@@ -7,176 +16,482 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(part_1_regions);
     cfg.service(part_1_regions_total);
     cfg.service(part_1_regions_top_list);
+    cfg.service(regions_tree);
+    cfg.service(export_orders);
+    cfg.service(export_regions);
+    cfg.configure(resources::configure);
 }
 
-#[post("/18/reset")]
-async fn part_1_reset(
-    pool: web::Data<PgPool>
-) -> impl Responder {
-    sqlx::query!("DROP TABLE IF EXISTS regions").execute(pool.as_ref()).await.unwrap();
-    sqlx::query!("DROP TABLE IF EXISTS orders").execute(pool.as_ref()).await.unwrap();
-
-    sqlx::query!("CREATE TABLE regions (
-        id INT PRIMARY KEY,
-        name VARCHAR(50)
-    )")
-        .execute(pool.as_ref())
-        .await
-        .unwrap();
-
-    sqlx::query!("CREATE TABLE orders (
-        id INT PRIMARY KEY,
-        region_id INT,
-        gift_name VARCHAR(50),
-        quantity INT
-    )")
-        .execute(pool.as_ref())
-        .await
-        .unwrap();
-
-    HttpResponse::Ok()
+#[derive(Deserialize)]
+struct RollupParams {
+    /// Whether a region's orders include those of its descendants.
+    #[serde(default)]
+    rollup: bool,
 }
 
-#[derive(serde::Deserialize)]
-struct Order {
-    id: i32,
-    region_id: i32,
-    gift_name: String,
-    quantity: i32,
+#[derive(Deserialize)]
+struct TopListParams {
+    #[serde(default)]
+    rollup: bool,
+    /// Whether to keep every gift tied with the last one in the list.
+    #[serde(default)]
+    ties: bool,
+    min_quantity: Option<i64>,
+    /// Whether to list gifts with their total quantity instead of by name only.
+    #[serde(default)]
+    detailed: bool,
 }
 
-#[derive(serde::Deserialize)]
-struct Region {
+#[derive(Serialize)]
+struct RegionNode {
     id: i32,
-    name: String,
+    name: Option<String>,
+    /// The total quantity ordered in the region and all of its descendants.
+    total: i64,
+    children: Vec<RegionNode>,
 }
 
-#[post("/18/orders")]
-async fn part_1_orders(
-    orders: web::Json<Vec<Order>>,
-    pool: web::Data<PgPool>,
-) -> impl Responder {
-    for order in orders.iter() {
-        sqlx::query!(
-            "INSERT INTO orders (id, region_id, gift_name, quantity) VALUES ($1, $2, $3, $4)",
-            order.id,
-            order.region_id,
-            order.gift_name,
-            order.quantity
-        )
-            .execute(pool.as_ref())
-            .await
-            .unwrap();
+/// Nests the regions under their parents, in id order, starting from the top-level regions.
+fn region_tree(rollups: Vec<RegionRollup>) -> Vec<RegionNode> {
+    let mut children: HashMap<Option<i32>, Vec<RegionRollup>> = HashMap::new();
+    for rollup in rollups {
+        children.entry(rollup.parent_id).or_default().push(rollup);
+    }
+
+    fn nodes(parent_id: Option<i32>, children: &mut HashMap<Option<i32>, Vec<RegionRollup>>) -> Vec<RegionNode> {
+        children
+            .remove(&parent_id)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|rollup| RegionNode {
+                children: nodes(Some(rollup.id), children),
+                id: rollup.id,
+                name: rollup.name,
+                total: rollup.total,
+            })
+            .collect()
     }
 
-    HttpResponse::Ok()
+    nodes(None, &mut children)
 }
 
-#[post("/18/regions")]
-async fn part_1_regions(
-    regions: web::Json<Vec<Region>>,
-    pool: web::Data<PgPool>,
-) -> impl Responder {
-    for region in regions.iter() {
-        sqlx::query!(
-            "INSERT INTO regions (id, name) VALUES ($1, $2)",
-            region.id,
-            region.name
-        )
-            .execute(pool.as_ref())
-            .await
-            .unwrap();
-    }
+#[post("/18/reset", wrap = "RequireMigrations")]
+async fn part_1_reset(
+    repository: web::Data<dyn OrderRepository>
+) -> Result<HttpResponse, AppError> {
+    repository.reset().await?;
 
-    HttpResponse::Ok()
+    Ok(HttpResponse::Ok().finish())
 }
 
-#[derive(serde::Serialize)]
-struct RegionTotal {
-    region: String,
-    total: i64,
+#[post("/18/orders", wrap = "RequireMigrations")]
+async fn part_1_orders(
+    req: HttpRequest,
+    payload: web::Payload,
+    params: web::Query<InsertParams>,
+    repository: web::Data<dyn OrderRepository>,
+) -> Result<HttpResponse, AppError> {
+    let orders: Vec<Order> = transfer::read_rows(&req, payload).await?;
+    let report = repository.insert_orders(&orders, params.on_conflict, MissingRegions::Reject).await?;
+
+    Ok(report.into_response())
 }
 
-#[get("/18/regions/total")]
-async fn part_1_regions_total(
-    pool: web::Data<PgPool>,
-) -> impl Responder {
-    let totals = sqlx::query!(
-        "SELECT regions.name region, SUM(orders.quantity) total FROM orders JOIN regions ON orders.region_id = regions.id GROUP BY regions.name ORDER BY regions.name"
-    )
-        .fetch_all(pool.as_ref())
-        .await
-        .unwrap()
-        .into_iter()
-        .map(|row| RegionTotal {
-            region: row.region.unwrap(),
-            total: row.total.unwrap(),
-        })
-        .collect::<Vec<RegionTotal>>();
+#[post("/18/regions", wrap = "RequireMigrations")]
+async fn part_1_regions(
+    req: HttpRequest,
+    payload: web::Payload,
+    repository: web::Data<dyn OrderRepository>,
+) -> Result<HttpResponse, AppError> {
+    let regions: Vec<Region> = transfer::read_rows(&req, payload).await?;
+    repository.insert_regions(&regions).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
 
-    HttpResponse::Ok().json(totals)
+#[get("/18/orders/export", wrap = "RequireMigrations")]
+async fn export_orders(
+    params: web::Query<ExportParams>,
+    repository: web::Data<dyn OrderRepository>,
+) -> Result<HttpResponse, AppError> {
+    Ok(transfer::write_rows(params.format, repository.all_orders().await?))
 }
 
-struct TopGift {
-    gift_name: String,
+#[get("/18/regions/export", wrap = "RequireMigrations")]
+async fn export_regions(
+    params: web::Query<ExportParams>,
+    repository: web::Data<dyn OrderRepository>,
+) -> Result<HttpResponse, AppError> {
+    Ok(transfer::write_rows(params.format, repository.all_regions().await?))
 }
 
-#[derive(serde::Serialize)]
-struct RegionTopList {
-    region: String,
-    top_gifts: Vec<String>,
+#[get("/18/regions/total", wrap = "RequireMigrations")]
+async fn part_1_regions_total(
+    params: web::Query<RollupParams>,
+    repository: web::Data<dyn OrderRepository>,
+) -> Result<HttpResponse, AppError> {
+    let totals = repository.region_totals(params.rollup).await?;
+
+    Ok(HttpResponse::Ok().json(totals))
 }
 
-#[get("/18/regions/top_list/{number}")]
+#[get("/18/regions/top_list/{number}", wrap = "RequireMigrations")]
 async fn part_1_regions_top_list(
-    pool: web::Data<PgPool>,
+    repository: web::Data<dyn OrderRepository>,
     number: web::Path<i64>,
-) -> impl Responder {
+    params: web::Query<TopListParams>,
+) -> Result<HttpResponse, AppError> {
     let number = number.into_inner();
+    if number < 0 {
+        return Err(AppError::BadRequest(format!("invalid number of gifts: {}", number)));
+    }
 
-    let region = sqlx::query!("SELECT id, name \"name!\" FROM regions")
-        .fetch_all(pool.as_ref())
-        .await
-        .unwrap()
+    let regions_top_list = repository
+        .top_gifts(&TopListQuery {
+            limit: number,
+            rollup: params.rollup,
+            ties: params.ties,
+            min_quantity: params.min_quantity,
+        })
+        .await?;
+
+    if params.detailed {
+        return Ok(HttpResponse::Ok().json(regions_top_list));
+    }
+
+    let regions_top_list: Vec<RegionTopList> = regions_top_list
         .into_iter()
-        .map(|row| Region {
-            id: row.id,
-            name: row.name,
+        .map(|top_list| RegionTopList {
+            region: top_list.region,
+            top_gifts: top_list.top_gifts.into_iter().map(|gift| gift.gift_name).collect(),
         })
-        .collect::<Vec<Region>>();
-
-    let mut regions_top_list = Vec::new();
-    for region in region {
-        let top_gifts = sqlx::query!(
-            "SELECT
-                gift_name \"gift_name!\"
-            FROM
-                orders
-            WHERE
-                region_id = $1
-            GROUP BY
-                gift_name
-            ORDER BY
-                SUM(quantity) DESC, gift_name
-            LIMIT $2",
-            region.id,
-            number
+        .collect();
+
+    Ok(HttpResponse::Ok().json(regions_top_list))
+}
+
+#[get("/18/regions/tree", wrap = "RequireMigrations")]
+async fn regions_tree(
+    repository: web::Data<dyn OrderRepository>,
+) -> Result<HttpResponse, AppError> {
+    let rollups = repository.region_rollups().await?;
+
+    Ok(HttpResponse::Ok().json(region_tree(rollups)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{App, body, test, web};
+    use serde_json::json;
+
+    use crate::orders::{MemoryOrderRepository, MissingRegions, OnConflict, Order, OrderRepository};
+
+    fn configure(cfg: &mut web::ServiceConfig) {
+        let repository: Arc<dyn OrderRepository> = Arc::new(MemoryOrderRepository::default());
+        cfg.app_data(web::Data::from(repository));
+        super::configure(cfg);
+    }
+
+    fn seed() -> [test::TestRequest; 2] {
+        [
+            test::TestRequest::post()
+                .uri("/18/regions")
+                .set_json(json!([
+                    { "id": 1, "name": "Pacific" },
+                    { "id": 2, "name": "Atlantic" },
+                    { "id": 3, "name": "Arctic" }
+                ])),
+            test::TestRequest::post()
+                .uri("/18/orders")
+                .set_json(json!([
+                    { "id": 1, "region_id": 1, "gift_name": "Board Game", "quantity": 3 },
+                    { "id": 2, "region_id": 1, "gift_name": "Drone", "quantity": 5 },
+                    { "id": 3, "region_id": 1, "gift_name": "Action Figure", "quantity": 5 },
+                    { "id": 4, "region_id": 2, "gift_name": "Board Game", "quantity": 8 }
+                ])),
+        ]
+    }
+
+    #[actix_web::test]
+    async fn test_regions_total() {
+        let app = test::init_service(App::new().configure(configure)).await;
+        for req in seed() {
+            let resp = test::call_service(&app, req.to_request()).await;
+            assert!(resp.status().is_success());
+        }
+
+        let req = test::TestRequest::get().uri("/18/regions/total").to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+
+        let body = resp.into_body();
+        let bytes = body::to_bytes(body).await.unwrap();
+        let response: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+
+        assert_eq!(response, json!([
+            { "region": "Atlantic", "total": 8 },
+            { "region": "Pacific", "total": 13 }
+        ]));
+    }
+
+    #[actix_web::test]
+    async fn test_regions_top_list() {
+        let app = test::init_service(App::new().configure(configure)).await;
+        for req in seed() {
+            let resp = test::call_service(&app, req.to_request()).await;
+            assert!(resp.status().is_success());
+        }
+
+        let req = test::TestRequest::get().uri("/18/regions/top_list/2").to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+
+        let body = resp.into_body();
+        let bytes = body::to_bytes(body).await.unwrap();
+        let response: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+
+        assert_eq!(response, json!([
+            { "region": "Arctic", "top_gifts": [] },
+            { "region": "Atlantic", "top_gifts": ["Board Game"] },
+            { "region": "Pacific", "top_gifts": ["Action Figure", "Drone"] }
+        ]));
+    }
+
+    #[actix_web::test]
+    async fn test_regions_top_list_options() {
+        let app = test::init_service(App::new().configure(configure)).await;
+        for req in seed() {
+            let resp = test::call_service(&app, req.to_request()).await;
+            assert!(resp.status().is_success());
+        }
+
+        let cases = [
+            ("/18/regions/top_list/1", json!([
+                { "region": "Arctic", "top_gifts": [] },
+                { "region": "Atlantic", "top_gifts": ["Board Game"] },
+                { "region": "Pacific", "top_gifts": ["Action Figure"] }
+            ])),
+            ("/18/regions/top_list/1?ties=true", json!([
+                { "region": "Arctic", "top_gifts": [] },
+                { "region": "Atlantic", "top_gifts": ["Board Game"] },
+                { "region": "Pacific", "top_gifts": ["Action Figure", "Drone"] }
+            ])),
+            ("/18/regions/top_list/0?ties=true", json!([
+                { "region": "Arctic", "top_gifts": [] },
+                { "region": "Atlantic", "top_gifts": [] },
+                { "region": "Pacific", "top_gifts": [] }
+            ])),
+            ("/18/regions/top_list/3?min_quantity=4&detailed=true", json!([
+                { "region": "Arctic", "top_gifts": [] },
+                { "region": "Atlantic", "top_gifts": [{ "gift_name": "Board Game", "quantity": 8 }] },
+                { "region": "Pacific", "top_gifts": [
+                    { "gift_name": "Action Figure", "quantity": 5 },
+                    { "gift_name": "Drone", "quantity": 5 }
+                ] }
+            ])),
+        ];
+
+        for (uri, expected) in cases {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            let resp = test::call_service(&app, req).await;
+            assert!(resp.status().is_success());
+
+            let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+            assert_eq!(serde_json::from_slice::<serde_json::Value>(&bytes).unwrap(), expected, "{}", uri);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_rejected_orders_and_regions() {
+        let app = test::init_service(App::new().configure(configure)).await;
+        for req in seed() {
+            let resp = test::call_service(&app, req.to_request()).await;
+            assert!(resp.status().is_success());
+        }
+
+        let req = test::TestRequest::post()
+            .uri("/18/orders?on_conflict=skip")
+            .set_json(json!([
+                { "id": 5, "region_id": 9, "gift_name": "Drone", "quantity": 1 },
+                { "id": 6, "region_id": 3, "gift_name": "Drone", "quantity": 1 }
+            ]))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+        let report: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(report["inserted"], 1);
+        assert_eq!(report["rows"], json!([{ "index": 0, "id": 5, "status": "rejected", "reason": "unknown region 9" }]));
+
+        let req = test::TestRequest::post()
+            .uri("/18/regions")
+            .set_json(json!([{ "id": 4, "name": "Indian" }, { "id": 1, "name": "Pacific" }]))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::get().uri("/18/regions/top_list/-1").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_placeholder_regions() {
+        let repository: Arc<dyn OrderRepository> = Arc::new(MemoryOrderRepository::default());
+        let app = test::init_service(
+            App::new().app_data(web::Data::from(repository.clone())).configure(super::configure),
         )
-            .fetch_all(pool.as_ref())
-            .await
+        .await;
+        for req in seed() {
+            let resp = test::call_service(&app, req.to_request()).await;
+            assert!(resp.status().is_success());
+        }
+
+        // day 13 orders create nameless regions, which the reports leave out
+        let order = Order { id: 5, region_id: 9, gift_name: Some("Drone".to_string()), quantity: 1 };
+        repository.insert_orders(&[order], OnConflict::Fail, MissingRegions::Create).await.unwrap();
+
+        let req = test::TestRequest::get().uri("/18/regions/top_list/1").to_request();
+        let bytes = body::to_bytes(test::call_service(&app, req).await.into_body()).await.unwrap();
+        let regions: Vec<String> = serde_json::from_slice::<Vec<serde_json::Value>>(&bytes)
             .unwrap()
             .into_iter()
-            .map(|row| TopGift {
-                gift_name: row.gift_name,
-            })
-            .collect::<Vec<TopGift>>();
+            .map(|top_list| top_list["region"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(regions, ["Arctic", "Atlantic", "Pacific"]);
+
+        let req = test::TestRequest::get().uri("/18/regions/tree").to_request();
+        let bytes = body::to_bytes(test::call_service(&app, req).await.into_body()).await.unwrap();
+        let tree: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(tree.as_array().unwrap().len(), 3);
+
+        // and only placeholders go without a name
+        let req = test::TestRequest::post().uri("/18/regions").set_json(json!([{ "id": 4 }])).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_region_hierarchy() {
+        let app = test::init_service(App::new().configure(configure)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/18/regions")
+            .set_json(json!([
+                { "id": 3, "name": "Los Angeles", "parent_id": 2 },
+                { "id": 2, "name": "California", "parent_id": 1 },
+                { "id": 1, "name": "USA" },
+                { "id": 4, "name": "Texas", "parent_id": 1 },
+                { "id": 6, "name": "New York", "parent_id": 1 },
+                { "id": 7, "name": "New York", "parent_id": 6 }
+            ]))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let req = test::TestRequest::post()
+            .uri("/18/orders")
+            .set_json(json!([
+                { "id": 1, "region_id": 3, "gift_name": "Drone", "quantity": 5 },
+                { "id": 2, "region_id": 2, "gift_name": "Board Game", "quantity": 2 },
+                { "id": 3, "region_id": 4, "gift_name": "Board Game", "quantity": 4 },
+                { "id": 4, "region_id": 7, "gift_name": "Drone", "quantity": 1 },
+                { "id": 5, "region_id": 7, "gift_name": "Kite", "quantity": 3 }
+            ]))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
 
-        regions_top_list.push(RegionTopList {
-            region: region.name,
-            top_gifts: top_gifts.into_iter().map(|gift| gift.gift_name).collect(),
-        });
+        let req = test::TestRequest::get().uri("/18/regions/total").to_request();
+        let bytes = body::to_bytes(test::call_service(&app, req).await.into_body()).await.unwrap();
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&bytes).unwrap(), json!([
+            { "region": "California", "total": 2 },
+            { "region": "Los Angeles", "total": 5 },
+            { "region": "New York", "total": 4 },
+            { "region": "Texas", "total": 4 }
+        ]));
+
+        let req = test::TestRequest::get().uri("/18/regions/total?rollup=true").to_request();
+        let bytes = body::to_bytes(test::call_service(&app, req).await.into_body()).await.unwrap();
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&bytes).unwrap(), json!([
+            { "region": "California", "total": 7 },
+            { "region": "Los Angeles", "total": 5 },
+            { "region": "New York", "total": 4 },
+            { "region": "New York", "total": 4 },
+            { "region": "Texas", "total": 4 },
+            { "region": "USA", "total": 15 }
+        ]));
+
+        let req = test::TestRequest::get().uri("/18/regions/top_list/1?rollup=true").to_request();
+        let bytes = body::to_bytes(test::call_service(&app, req).await.into_body()).await.unwrap();
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&bytes).unwrap(), json!([
+            { "region": "California", "top_gifts": ["Drone"] },
+            { "region": "Los Angeles", "top_gifts": ["Drone"] },
+            { "region": "New York", "top_gifts": ["Kite"] },
+            { "region": "New York", "top_gifts": ["Kite"] },
+            { "region": "Texas", "top_gifts": ["Board Game"] },
+            { "region": "USA", "top_gifts": ["Board Game"] }
+        ]));
+
+        let req = test::TestRequest::get().uri("/18/regions/tree").to_request();
+        let bytes = body::to_bytes(test::call_service(&app, req).await.into_body()).await.unwrap();
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&bytes).unwrap(), json!([
+            { "id": 1, "name": "USA", "total": 15, "children": [
+                { "id": 2, "name": "California", "total": 7, "children": [
+                    { "id": 3, "name": "Los Angeles", "total": 5, "children": [] }
+                ] },
+                { "id": 4, "name": "Texas", "total": 4, "children": [] },
+                { "id": 6, "name": "New York", "total": 4, "children": [
+                    { "id": 7, "name": "New York", "total": 4, "children": [] }
+                ] }
+            ] }
+        ]));
+
+        for regions in [
+            json!([{ "id": 8, "name": "Atlantis", "parent_id": 9 }, { "id": 9, "name": "Lemuria", "parent_id": 8 }]),
+            json!([{ "id": 8, "name": "Atlantis", "parent_id": 8 }]),
+            json!([{ "id": 8, "name": "Atlantis", "parent_id": 99 }]),
+        ] {
+            let req = test::TestRequest::post().uri("/18/regions").set_json(regions).to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+        }
+
+        let req = test::TestRequest::delete().uri("/18/regions/1").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::CONFLICT);
     }
-    regions_top_list.sort_unstable_by_key(|r| r.region.clone());
 
-    HttpResponse::Ok().json(regions_top_list)
+    #[actix_web::test]
+    async fn test_ndjson_import_and_csv_export() {
+        let app = test::init_service(App::new().configure(configure)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/18/regions")
+            .insert_header(("content-type", "application/x-ndjson"))
+            .set_payload("{\"id\":2,\"name\":\"Atlantic\"}\n\n{\"id\":1,\"name\":\"Pacific\"}\n")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let req = test::TestRequest::get().uri("/18/regions/export?format=csv").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        assert_eq!(resp.headers().get("content-type").unwrap(), "text/csv");
+
+        let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(String::from_utf8(bytes.to_vec()).unwrap(), "id,name,parent_id\n1,Pacific,\n2,Atlantic,\n");
+
+        let req = test::TestRequest::get().uri("/18/orders/export").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(String::from_utf8(bytes.to_vec()).unwrap(), "[]");
+    }
 }
@@ -0,0 +1,465 @@
+use actix_web::{delete, get, patch, put, web, HttpResponse};
+use serde::Deserialize;
+
+use crate::error::AppError;
+use crate::migrations::RequireMigrations;
+use crate::orders::{
+    Direction, OrderListQuery, OrderPatch, OrderRepository, OrderSort, RegionListQuery, RegionPatch, RegionSort,
+};
+use crate::pagination::{self, page_size, Page};
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_orders);
+    cfg.service(get_order);
+    cfg.service(put_order);
+    cfg.service(patch_order);
+    cfg.service(delete_order);
+    cfg.service(list_regions);
+    cfg.service(get_region);
+    cfg.service(put_region);
+    cfg.service(patch_region);
+    cfg.service(delete_region);
+}
+
+fn order_not_found(id: i32) -> AppError {
+    AppError::NotFound(format!("order {} not found", id))
+}
+
+fn region_not_found(id: i32) -> AppError {
+    AppError::NotFound(format!("region {} not found", id))
+}
+
+#[derive(Deserialize)]
+struct OrderListParams {
+    #[serde(default)]
+    sort: OrderSort,
+    #[serde(default)]
+    direction: Direction,
+    after: Option<String>,
+    limit: Option<i64>,
+    region_id: Option<i32>,
+    gift_prefix: Option<String>,
+    min_quantity: Option<i32>,
+    max_quantity: Option<i32>,
+}
+
+/// The fields of an order, all required when replacing it.
+#[derive(Deserialize)]
+struct OrderFields {
+    region_id: i32,
+    gift_name: String,
+    quantity: i32,
+}
+
+#[get("/18/orders", wrap = "RequireMigrations")]
+async fn list_orders(
+    params: web::Query<OrderListParams>,
+    repository: web::Data<dyn OrderRepository>,
+) -> Result<HttpResponse, AppError> {
+    let params = params.into_inner();
+    let query = OrderListQuery {
+        sort: params.sort,
+        direction: params.direction,
+        after: params.after
+            .as_deref()
+            .map(|after| pagination::decode_sorted_cursor(after, (params.sort, params.direction)))
+            .transpose()?,
+        limit: page_size(params.limit)?,
+        region_id: params.region_id,
+        gift_prefix: params.gift_prefix,
+        min_quantity: params.min_quantity,
+        max_quantity: params.max_quantity,
+    };
+
+    let rows = repository.list_orders(&query).await?;
+    Ok(HttpResponse::Ok().json(Page::sorted(rows, query.limit, (query.sort, query.direction))?))
+}
+
+#[get("/18/orders/{id:-?\\d+}", wrap = "RequireMigrations")]
+async fn get_order(id: web::Path<i32>, repository: web::Data<dyn OrderRepository>) -> Result<HttpResponse, AppError> {
+    let id = id.into_inner();
+    let order = repository.get_order(id).await?.ok_or_else(|| order_not_found(id))?;
+
+    Ok(HttpResponse::Ok().json(order))
+}
+
+#[put("/18/orders/{id:-?\\d+}", wrap = "RequireMigrations")]
+async fn put_order(
+    id: web::Path<i32>,
+    fields: web::Json<OrderFields>,
+    repository: web::Data<dyn OrderRepository>,
+) -> Result<HttpResponse, AppError> {
+    let id = id.into_inner();
+    let fields = fields.into_inner();
+    let patch = OrderPatch {
+        region_id: Some(fields.region_id),
+        gift_name: Some(fields.gift_name),
+        quantity: Some(fields.quantity),
+    };
+    let order = repository.update_order(id, &patch).await?.ok_or_else(|| order_not_found(id))?;
+
+    Ok(HttpResponse::Ok().json(order))
+}
+
+#[patch("/18/orders/{id:-?\\d+}", wrap = "RequireMigrations")]
+async fn patch_order(
+    id: web::Path<i32>,
+    patch: web::Json<OrderPatch>,
+    repository: web::Data<dyn OrderRepository>,
+) -> Result<HttpResponse, AppError> {
+    let id = id.into_inner();
+    let order = repository.update_order(id, &patch).await?.ok_or_else(|| order_not_found(id))?;
+
+    Ok(HttpResponse::Ok().json(order))
+}
+
+#[delete("/18/orders/{id:-?\\d+}", wrap = "RequireMigrations")]
+async fn delete_order(id: web::Path<i32>, repository: web::Data<dyn OrderRepository>) -> Result<HttpResponse, AppError> {
+    let id = id.into_inner();
+    if !repository.delete_order(id).await? {
+        return Err(order_not_found(id));
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Deserialize)]
+struct RegionListParams {
+    #[serde(default)]
+    sort: RegionSort,
+    #[serde(default)]
+    direction: Direction,
+    after: Option<String>,
+    limit: Option<i64>,
+    name_prefix: Option<String>,
+}
+
+/// The fields of a region when replacing it; leaving out the parent makes it a top-level region.
+#[derive(Deserialize)]
+struct RegionFields {
+    name: String,
+    #[serde(default)]
+    parent_id: Option<i32>,
+}
+
+#[get("/18/regions", wrap = "RequireMigrations")]
+async fn list_regions(
+    params: web::Query<RegionListParams>,
+    repository: web::Data<dyn OrderRepository>,
+) -> Result<HttpResponse, AppError> {
+    let params = params.into_inner();
+    let query = RegionListQuery {
+        sort: params.sort,
+        direction: params.direction,
+        after: params.after
+            .as_deref()
+            .map(|after| pagination::decode_sorted_cursor(after, (params.sort, params.direction)))
+            .transpose()?,
+        limit: page_size(params.limit)?,
+        name_prefix: params.name_prefix,
+    };
+
+    let rows = repository.list_regions(&query).await?;
+    Ok(HttpResponse::Ok().json(Page::sorted(rows, query.limit, (query.sort, query.direction))?))
+}
+
+#[get("/18/regions/{id:-?\\d+}", wrap = "RequireMigrations")]
+async fn get_region(id: web::Path<i32>, repository: web::Data<dyn OrderRepository>) -> Result<HttpResponse, AppError> {
+    let id = id.into_inner();
+    let region = repository.get_region(id).await?.ok_or_else(|| region_not_found(id))?;
+
+    Ok(HttpResponse::Ok().json(region))
+}
+
+#[put("/18/regions/{id:-?\\d+}", wrap = "RequireMigrations")]
+async fn put_region(
+    id: web::Path<i32>,
+    fields: web::Json<RegionFields>,
+    repository: web::Data<dyn OrderRepository>,
+) -> Result<HttpResponse, AppError> {
+    let id = id.into_inner();
+    let fields = fields.into_inner();
+    let patch = RegionPatch { name: Some(fields.name), parent_id: Some(fields.parent_id) };
+    let region = repository.update_region(id, &patch).await?.ok_or_else(|| region_not_found(id))?;
+
+    Ok(HttpResponse::Ok().json(region))
+}
+
+/// A `parent_id` of `null` makes the region a top-level one, while leaving it out keeps the
+/// current parent.
+#[patch("/18/regions/{id:-?\\d+}", wrap = "RequireMigrations")]
+async fn patch_region(
+    id: web::Path<i32>,
+    patch: web::Json<RegionPatch>,
+    repository: web::Data<dyn OrderRepository>,
+) -> Result<HttpResponse, AppError> {
+    let id = id.into_inner();
+    let region = repository.update_region(id, &patch).await?.ok_or_else(|| region_not_found(id))?;
+
+    Ok(HttpResponse::Ok().json(region))
+}
+
+#[delete("/18/regions/{id:-?\\d+}", wrap = "RequireMigrations")]
+async fn delete_region(id: web::Path<i32>, repository: web::Data<dyn OrderRepository>) -> Result<HttpResponse, AppError> {
+    let id = id.into_inner();
+    if !repository.delete_region(id).await? {
+        return Err(region_not_found(id));
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::http::StatusCode;
+    use actix_web::{body, test, web, App};
+    use serde_json::{json, Value};
+
+    use crate::orders::{MemoryOrderRepository, OrderRepository};
+
+    fn configure(cfg: &mut web::ServiceConfig) {
+        let repository: Arc<dyn OrderRepository> = Arc::new(MemoryOrderRepository::default());
+        cfg.app_data(web::Data::from(repository));
+        super::super::configure(cfg);
+    }
+
+    fn seed() -> [test::TestRequest; 2] {
+        [
+            test::TestRequest::post()
+                .uri("/18/regions")
+                .set_json(json!([{ "id": 1, "name": "Pacific" }, { "id": 2, "name": "Atlantic" }])),
+            test::TestRequest::post()
+                .uri("/18/orders")
+                .set_json(json!([
+                    { "id": 1, "region_id": 1, "gift_name": "Drone", "quantity": 5 },
+                    { "id": 2, "region_id": 1, "gift_name": "Board Game", "quantity": 3 },
+                    { "id": 3, "region_id": 2, "gift_name": "Drone", "quantity": 5 },
+                    { "id": 4, "region_id": 2, "gift_name": "Doll", "quantity": 1 },
+                    { "id": 5, "region_id": 1, "gift_name": "Doll", "quantity": 7 }
+                ])),
+        ]
+    }
+
+    #[actix_web::test]
+    async fn test_order_crud() {
+        let app = test::init_service(App::new().configure(configure)).await;
+        for req in seed() {
+            let resp = test::call_service(&app, req.to_request()).await;
+            assert!(resp.status().is_success());
+        }
+
+        let req = test::TestRequest::get().uri("/18/orders/3").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(
+            serde_json::from_slice::<Value>(&bytes).unwrap(),
+            json!({ "id": 3, "region_id": 2, "gift_name": "Drone", "quantity": 5 })
+        );
+
+        let req = test::TestRequest::patch().uri("/18/orders/3").set_json(json!({ "quantity": 9 })).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(serde_json::from_slice::<Value>(&bytes).unwrap()["quantity"], 9);
+
+        let req = test::TestRequest::put()
+            .uri("/18/orders/3")
+            .set_json(json!({ "region_id": 1, "gift_name": "Kite", "quantity": 2 }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(
+            serde_json::from_slice::<Value>(&bytes).unwrap(),
+            json!({ "id": 3, "region_id": 1, "gift_name": "Kite", "quantity": 2 })
+        );
+
+        let req = test::TestRequest::patch().uri("/18/orders/3").set_json(json!({ "region_id": 9 })).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+        let req = test::TestRequest::delete().uri("/18/orders/3").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        for req in [
+            test::TestRequest::get().uri("/18/orders/3"),
+            test::TestRequest::delete().uri("/18/orders/3"),
+            test::TestRequest::patch().uri("/18/orders/3").set_json(json!({})),
+        ] {
+            let resp = test::call_service(&app, req.to_request()).await;
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_region_crud() {
+        let app = test::init_service(App::new().configure(configure)).await;
+        for req in seed() {
+            let resp = test::call_service(&app, req.to_request()).await;
+            assert!(resp.status().is_success());
+        }
+
+        let req = test::TestRequest::put().uri("/18/regions/2").set_json(json!({ "name": "Indian" })).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(serde_json::from_slice::<Value>(&bytes).unwrap(), json!({ "id": 2, "name": "Indian", "parent_id": null }));
+
+        let long_name = "x".repeat(51);
+        for req in [
+            test::TestRequest::put().uri("/18/regions/2").set_json(json!({ "name": long_name })),
+            test::TestRequest::post().uri("/18/regions").set_json(json!([{ "id": 3, "name": long_name }])),
+        ] {
+            let resp = test::call_service(&app, req.to_request()).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        }
+
+        let req = test::TestRequest::delete().uri("/18/regions/2").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+        for id in [3, 4] {
+            let req = test::TestRequest::delete().uri(&format!("/18/orders/{}", id)).to_request();
+            let resp = test::call_service(&app, req).await;
+            assert!(resp.status().is_success());
+        }
+
+        let req = test::TestRequest::delete().uri("/18/regions/2").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        let req = test::TestRequest::get().uri("/18/regions/2").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        // the fixed routes still win over the id routes
+        let req = test::TestRequest::get().uri("/18/regions/total").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+    }
+
+    #[actix_web::test]
+    async fn test_region_parents() {
+        let app = test::init_service(App::new().configure(configure)).await;
+        for req in seed() {
+            let resp = test::call_service(&app, req.to_request()).await;
+            assert!(resp.status().is_success());
+        }
+        let req = test::TestRequest::post().uri("/18/regions").set_json(json!([{ "id": 3, "name": "Arctic" }]));
+        assert!(test::call_service(&app, req.to_request()).await.status().is_success());
+
+        let cases = [
+            (
+                test::TestRequest::patch().set_json(json!({ "parent_id": 1 })),
+                "/18/regions/2",
+                json!({ "id": 2, "name": "Atlantic", "parent_id": 1 }),
+            ),
+            (
+                test::TestRequest::patch().set_json(json!({ "name": "North Atlantic" })),
+                "/18/regions/2",
+                json!({ "id": 2, "name": "North Atlantic", "parent_id": 1 }),
+            ),
+            (
+                test::TestRequest::put().set_json(json!({ "name": "Arctic", "parent_id": 2 })),
+                "/18/regions/3",
+                json!({ "id": 3, "name": "Arctic", "parent_id": 2 }),
+            ),
+            (
+                test::TestRequest::patch().set_json(json!({ "parent_id": null })),
+                "/18/regions/2",
+                json!({ "id": 2, "name": "North Atlantic", "parent_id": null }),
+            ),
+            (
+                test::TestRequest::put().set_json(json!({ "name": "Arctic" })),
+                "/18/regions/3",
+                json!({ "id": 3, "name": "Arctic", "parent_id": null }),
+            ),
+        ];
+        for (req, uri, expected) in cases {
+            let resp = test::call_service(&app, req.uri(uri).to_request()).await;
+            assert!(resp.status().is_success());
+            let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+            assert_eq!(serde_json::from_slice::<Value>(&bytes).unwrap(), expected);
+        }
+
+        // Pacific > Atlantic > Arctic
+        for (uri, parent_id) in [("/18/regions/2", 1), ("/18/regions/3", 2)] {
+            let req = test::TestRequest::patch().uri(uri).set_json(json!({ "parent_id": parent_id }));
+            assert!(test::call_service(&app, req.to_request()).await.status().is_success());
+        }
+        for req in [
+            test::TestRequest::patch().uri("/18/regions/1").set_json(json!({ "parent_id": 3 })),
+            test::TestRequest::put().uri("/18/regions/1").set_json(json!({ "name": "Pacific", "parent_id": 1 })),
+            test::TestRequest::patch().uri("/18/regions/1").set_json(json!({ "parent_id": 9 })),
+        ] {
+            let resp = test::call_service(&app, req.to_request()).await;
+            assert_eq!(resp.status(), StatusCode::CONFLICT);
+        }
+
+        let req = test::TestRequest::patch().uri("/18/regions/9").set_json(json!({ "parent_id": 1 }));
+        assert_eq!(test::call_service(&app, req.to_request()).await.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_keyset_pagination() {
+        let app = test::init_service(App::new().configure(configure)).await;
+        for req in seed() {
+            let resp = test::call_service(&app, req.to_request()).await;
+            assert!(resp.status().is_success());
+        }
+
+        let mut ids = Vec::new();
+        let mut cursors = Vec::new();
+        let mut uri = "/18/orders?sort=quantity&direction=desc&limit=2".to_string();
+        loop {
+            let req = test::TestRequest::get().uri(&uri).to_request();
+            let resp = test::call_service(&app, req).await;
+            assert!(resp.status().is_success());
+
+            let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+            let page: Value = serde_json::from_slice(&bytes).unwrap();
+            ids.extend(page["items"].as_array().unwrap().iter().map(|order| order["id"].as_i64().unwrap()));
+
+            match page["next"].as_str() {
+                Some(next) => {
+                    cursors.push(next.to_string());
+                    uri = format!("/18/orders?sort=quantity&direction=desc&limit=2&after={}", next);
+                }
+                None => break,
+            }
+        }
+        // the two orders with a quantity of 5 are ordered by descending id
+        assert_eq!(ids, [5, 3, 1, 2, 4]);
+
+        // a cursor only continues the listing it was issued for
+        for params in ["sort=quantity", "sort=id&direction=desc"] {
+            let req = test::TestRequest::get().uri(&format!("/18/orders?{}&after={}", params, cursors[0])).to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        }
+
+        let req = test::TestRequest::get().uri("/18/orders?gift_prefix=D&region_id=1&sort=gift_name").to_request();
+        let resp = test::call_service(&app, req).await;
+        let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+        let page: Value = serde_json::from_slice(&bytes).unwrap();
+        let ids: Vec<i64> = page["items"].as_array().unwrap().iter().map(|order| order["id"].as_i64().unwrap()).collect();
+        assert_eq!(ids, [5, 1]);
+        assert!(page["next"].is_null());
+
+        let req = test::TestRequest::get().uri("/18/regions?sort=name").to_request();
+        let resp = test::call_service(&app, req).await;
+        let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(serde_json::from_slice::<Value>(&bytes).unwrap(), json!({
+            "items": [{ "id": 2, "name": "Atlantic", "parent_id": null }, { "id": 1, "name": "Pacific", "parent_id": null }],
+            "next": null
+        }));
+
+        let req = test::TestRequest::get().uri("/18/orders?after=garbage").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -4,6 +4,8 @@ use actix_multipart::form::{tempfile::TempFile, MultipartForm};
 use actix_web::{get, HttpResponse, post, Responder, web};
 use image::GenericImageView;
 
+use crate::error::AppError;
+
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(part_1);
     cfg.service(part_2);
@@ -20,9 +22,9 @@ struct File {
 }
 
 #[post("/11/red_pixels")]
-async fn part_2(MultipartForm(file): MultipartForm<File>) -> impl Responder {
+async fn part_2(MultipartForm(file): MultipartForm<File>) -> Result<HttpResponse, AppError> {
     let reader = BufReader::new(&file.image.file);
-    let image = image::load(reader, image::ImageFormat::Png).unwrap();
+    let image = image::load(reader, image::ImageFormat::Png).map_err(AppError::bad_request)?;
     let mut count = 0;
 
     for pixel in image.pixels() {
@@ -32,12 +34,12 @@ async fn part_2(MultipartForm(file): MultipartForm<File>) -> impl Responder {
         }
     }
 
-    HttpResponse::Ok().body(format!("{}", count))
+    Ok(HttpResponse::Ok().body(format!("{}", count)))
 }
 
 #[cfg(test)]
 mod tests {
-    use actix_web::{body, test, App};
+    use actix_web::{test, App};
 
     #[actix_web::test]
     async fn test_part_1() {
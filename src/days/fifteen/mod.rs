@@ -1,9 +1,11 @@
 use std::str::FromStr;
 
-use actix_web::{HttpResponse, post, Responder, web};
+use actix_web::{HttpResponse, post, web};
 use serde_json::json;
 use unicode_segmentation::UnicodeSegmentation;
 
+use crate::error::AppError;
+
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(part_1);
     cfg.service(part_2);
@@ -17,95 +19,100 @@ struct Content {
 #[post("/15/nice")]
 async fn part_1(
     content: web::Json<Content>
-) -> impl Responder {
+) -> Result<HttpResponse, AppError> {
     let password = content.input.clone();
 
     let re = fancy_regex::Regex::new(r"^(?=.*[aeiouy].*[aeiouy].*[aeiouy])(?=.*([a-z])\1)(?!.*(?:ab|cd|pq|xy)).*$").unwrap();
 
-    return match re.is_match(&password).unwrap() {
+    Ok(match re.is_match(&password).map_err(AppError::bad_request)? {
         true => HttpResponse::Ok().json(json!({"result": "nice"})),
         false => HttpResponse::BadRequest().json(json!({"result": "naughty"})),
-    };
+    })
 }
 
-fn check_rules(password: String) -> Option<i32> {
+fn check_rules(password: String) -> Result<Option<i32>, AppError> {
     // Rule 1: must be at least 8 characters long
     if password.len() < 8 {
-        return Some(1);
+        return Ok(Some(1));
     }
 
     // Rule 2: must contain uppercase letters, lowercase letters, and digits
     let re = fancy_regex::Regex::new(r"^(?=.*[a-z])(?=.*[A-Z])(?=.*\d).+$").unwrap();
-    if !re.is_match(&password).unwrap() {
-        return Some(2);
+    if !re.is_match(&password).map_err(AppError::bad_request)? {
+        return Ok(Some(2));
     }
     // Rule 3: must contain at least 5 digits
     let re = fancy_regex::Regex::new(r"^(.*\d.*){5,}$").unwrap();
-    if !re.is_match(&password).unwrap() {
-        return Some(3);
+    if !re.is_match(&password).map_err(AppError::bad_request)? {
+        return Ok(Some(3));
     }
     // Rule 4: all integers (sequences of consecutive digits) in the string must add up to 2023
     let re = fancy_regex::Regex::new(r"\d+").unwrap();
-    let mut sum = 0;
+    let mut sum: i32 = 0;
     for mat in re.find_iter(password.as_str()) {
-        sum += i32::from_str(mat.unwrap().as_str()).unwrap();
+        let mat = mat.map_err(AppError::bad_request)?;
+        // a number that does not fit in an i32 can never add up to 2023
+        match i32::from_str(mat.as_str()).ok().and_then(|n| sum.checked_add(n)) {
+            Some(n) => sum = n,
+            None => return Ok(Some(4)),
+        }
     }
 
     if sum != 2023 {
-        return Some(4);
+        return Ok(Some(4));
     }
 
     // Rule 5: must contain the letters j, o, and y in that order and in no other order
     // get index of j, o, and y, if not present return 5 don't unwrap
     let j = match password.find("j") {
         Some(i) => i,
-        None => return Some(5),
+        None => return Ok(Some(5)),
     };
     let o = match password.find("o") {
         Some(i) => i,
-        None => return Some(5),
+        None => return Ok(Some(5)),
     };
     let y = match password.find("y") {
         Some(i) => i,
-        None => return Some(5),
+        None => return Ok(Some(5)),
     };
     let re = fancy_regex::Regex::new(r"j.+o.+y").unwrap();
-    if !re.is_match(&password).unwrap() {
-        return Some(5);
+    if !re.is_match(&password).map_err(AppError::bad_request)? {
+        return Ok(Some(5));
     }
 
     if j > o || o > y {
-        return Some(5);
+        return Ok(Some(5));
     }
     // Rule 6: must contain a letter that repeats with exactly one other letter between them (like xyx)
     let re = fancy_regex::Regex::new(r"([a-zA-Z]).\1").unwrap();
-    if !re.is_match(&password).unwrap() {
-        return Some(6);
+    if !re.is_match(&password).map_err(AppError::bad_request)? {
+        return Ok(Some(6));
     }
     // Rule 7: must contain at least one unicode character in the range [U+2980, U+2BFF]
     if !password.chars().any(|c| ('\u{2980}'..='\u{2BFF}').contains(&c)) {
-        return Some(7);
+        return Ok(Some(7));
     }
     // Rule 8: must contain at least one emoji
 
     if !password.graphemes(true).any(|el| emojis::get(el).is_some()) {
-        return Some(8);
+        return Ok(Some(8));
     }
 
     // Rule 9: the hexadecimal representation of the sha256 hash of the string must end with an 'a'
     if !sha256::digest(password).ends_with("a") {
-        return Some(9);
+        return Ok(Some(9));
     }
 
-    None
+    Ok(None)
 }
 
 #[post("/15/game")]
 async fn part_2(
     content: web::Json<Content>
-) -> impl Responder {
+) -> Result<HttpResponse, AppError> {
     let password = content.input.clone();
-    return match check_rules(password) {
+    Ok(match check_rules(password)? {
         Some(i) => match i {
             1 => HttpResponse::BadRequest().json(json!({"result": "naughty", "reason": "8 chars"})),
             2 => HttpResponse::BadRequest().json(json!({"result": "naughty", "reason": "more types of chars"})),
@@ -119,5 +126,5 @@ async fn part_2(
             _ => HttpResponse::InternalServerError().json(json!({"result": "naughty", "reason": "unknown"})),
         }
         None => HttpResponse::Ok().json(json!({"result": "nice", "reason": "that's a nice password"})),
-    };
+    })
 }
@@ -2,6 +2,8 @@ use actix_web::{post, web, HttpResponse, Responder};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
+use crate::error::AppError;
+
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(part_1);
     cfg.service(part_2);
@@ -41,20 +43,22 @@ async fn part_1(deers: web::Json<Vec<Reindeer>>) -> impl Responder {
 }
 
 #[post("/4/contest")]
-async fn part_2(deers: web::Json<Vec<ReindeerContest>>) -> impl Responder {
+async fn part_2(deers: web::Json<Vec<ReindeerContest>>) -> Result<HttpResponse, AppError> {
+    let no_reindeer = || AppError::bad_request("no reindeer in the contest");
+
     let fastest = deers
         .iter()
         .max_by(|a, b| a.speed.total_cmp(&b.speed))
-        .unwrap();
-    let tallest = deers.iter().max_by(|a, b| a.height.cmp(&b.height)).unwrap();
+        .ok_or_else(no_reindeer)?;
+    let tallest = deers.iter().max_by(|a, b| a.height.cmp(&b.height)).ok_or_else(no_reindeer)?;
     let magician = deers
         .iter()
         .max_by(|a, b| a.snow_magic_power.cmp(&b.snow_magic_power))
-        .unwrap();
+        .ok_or_else(no_reindeer)?;
     let consumer = deers
         .iter()
         .max_by(|a, b| a.candies_eaten.cmp(&b.candies_eaten))
-        .unwrap();
+        .ok_or_else(no_reindeer)?;
 
     let response = ReindeerContestResponse {
         fastest: format!(
@@ -75,7 +79,7 @@ async fn part_2(deers: web::Json<Vec<ReindeerContest>>) -> impl Responder {
         ),
     };
 
-    HttpResponse::Ok().body(json!(response).to_string())
+    Ok(HttpResponse::Ok().body(json!(response).to_string()))
 }
 
 #[cfg(test)]
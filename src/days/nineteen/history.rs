@@ -0,0 +1,370 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use sqlx::PgPool;
+use tokio::sync::Mutex;
+
+use crate::error::AppError;
+
+use super::limits;
+use super::TMessage;
+
+const DEFAULT_HISTORY_SIZE: usize = 100;
+
+fn message_not_found(id: i64) -> AppError {
+    AppError::NotFound(format!("message {} not found", id))
+}
+
+fn not_author(id: i64) -> AppError {
+    AppError::Forbidden(format!("message {} was sent by another user", id))
+}
+
+/// The most recent messages of every room, up to a fixed number per room.
+#[async_trait]
+pub trait ChatHistory: Send + Sync {
+    /// Records a message, assigning its id and timestamp, and forgets the oldest one of the room
+    /// when it is full.
+    async fn append(&self, room_id: i64, user: &str, message: &str) -> Result<TMessage, AppError>;
+    /// The whole history of the room, oldest first.
+    async fn all(&self, room_id: i64) -> Result<Vec<TMessage>, AppError>;
+    /// Up to `limit + 1` messages older than `before`, newest first, the extra one telling whether
+    /// there is another page.
+    async fn page(&self, room_id: i64, before: Option<i64>, limit: i64) -> Result<Vec<TMessage>, AppError>;
+    /// Replaces the text of a message `user` sent.
+    async fn edit(&self, room_id: i64, id: i64, user: &str, message: &str) -> Result<TMessage, AppError>;
+    /// Forgets a message `user` sent.
+    async fn delete(&self, room_id: i64, id: i64, user: &str) -> Result<(), AppError>;
+}
+
+/// Builds the history from the environment: kept where `CHAT_HISTORY_STORE` says, `memory` (the
+/// default) or `postgres`, with `CHAT_HISTORY_SIZE` messages per room. Fails on an unknown store
+/// or a size that cannot be parsed.
+pub fn from_env(pool: PgPool) -> Result<Arc<dyn ChatHistory>, AppError> {
+    dotenv::dotenv().ok();
+
+    let size = limits::env_or("CHAT_HISTORY_SIZE", DEFAULT_HISTORY_SIZE)?;
+
+    match std::env::var("CHAT_HISTORY_STORE").as_deref() {
+        Err(_) | Ok("memory") => Ok(Arc::new(MemoryChatHistory::new(size))),
+        Ok("postgres") => Ok(Arc::new(PgChatHistory::new(pool, size))),
+        Ok(other) => Err(AppError::Internal(format!("unknown chat history store: {}", other))),
+    }
+}
+
+#[derive(Default)]
+struct RoomHistory {
+    last_id: i64,
+    messages: VecDeque<TMessage>,
+}
+
+impl RoomHistory {
+    fn find_mut(&mut self, id: i64, user: &str) -> Result<&mut TMessage, AppError> {
+        let message = self.messages
+            .iter_mut()
+            .find(|message| message.id == id)
+            .ok_or_else(|| message_not_found(id))?;
+        if message.user != user {
+            return Err(not_author(id));
+        }
+        Ok(message)
+    }
+}
+
+pub struct MemoryChatHistory {
+    size: usize,
+    rooms: Mutex<HashMap<i64, RoomHistory>>,
+}
+
+impl MemoryChatHistory {
+    pub fn new(size: usize) -> Self {
+        MemoryChatHistory {
+            size,
+            rooms: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl ChatHistory for MemoryChatHistory {
+    async fn append(&self, room_id: i64, user: &str, message: &str) -> Result<TMessage, AppError> {
+        let mut rooms = self.rooms.lock().await;
+        let room = rooms.entry(room_id).or_default();
+
+        room.last_id += 1;
+        let message = TMessage {
+            id: room.last_id,
+            user: user.to_string(),
+            message: message.to_string(),
+            timestamp: Utc::now(),
+            edited_at: None,
+        };
+        room.messages.push_back(message.clone());
+        while room.messages.len() > self.size {
+            room.messages.pop_front();
+        }
+        Ok(message)
+    }
+
+    async fn all(&self, room_id: i64) -> Result<Vec<TMessage>, AppError> {
+        let rooms = self.rooms.lock().await;
+        Ok(rooms.get(&room_id).map(|room| room.messages.iter().cloned().collect()).unwrap_or_default())
+    }
+
+    async fn page(&self, room_id: i64, before: Option<i64>, limit: i64) -> Result<Vec<TMessage>, AppError> {
+        let rooms = self.rooms.lock().await;
+        let Some(room) = rooms.get(&room_id) else {
+            return Ok(Vec::new());
+        };
+
+        Ok(room.messages
+            .iter()
+            .rev()
+            .filter(|message| before.is_none_or(|before| message.id < before))
+            .take(limit as usize + 1)
+            .cloned()
+            .collect())
+    }
+
+    async fn edit(&self, room_id: i64, id: i64, user: &str, message: &str) -> Result<TMessage, AppError> {
+        let mut rooms = self.rooms.lock().await;
+        let room = rooms.get_mut(&room_id).ok_or_else(|| message_not_found(id))?;
+
+        let edited = room.find_mut(id, user)?;
+        edited.message = message.to_string();
+        edited.edited_at = Some(Utc::now());
+        Ok(edited.clone())
+    }
+
+    async fn delete(&self, room_id: i64, id: i64, user: &str) -> Result<(), AppError> {
+        let mut rooms = self.rooms.lock().await;
+        let room = rooms.get_mut(&room_id).ok_or_else(|| message_not_found(id))?;
+
+        room.find_mut(id, user)?;
+        room.messages.retain(|message| message.id != id);
+        Ok(())
+    }
+}
+
+pub struct PgChatHistory {
+    pool: PgPool,
+    size: usize,
+}
+
+impl PgChatHistory {
+    pub fn new(pool: PgPool, size: usize) -> Self {
+        PgChatHistory { pool, size }
+    }
+
+    /// Fails unless the room has a message with this id sent by `user`.
+    async fn check_author(&self, room_id: i64, id: i64, user: &str) -> Result<(), AppError> {
+        let author = sqlx::query!(
+            "SELECT user_name FROM chat_messages WHERE room_id = $1 AND seq = $2",
+            room_id,
+            id
+        )
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or_else(|| message_not_found(id))?
+            .user_name;
+
+        if author != user {
+            return Err(not_author(id));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ChatHistory for PgChatHistory {
+    async fn append(&self, room_id: i64, user: &str, message: &str) -> Result<TMessage, AppError> {
+        let mut tx = self.pool.begin().await?;
+
+        // Ids come from a sequence shared by all rooms; locking the room until the commit makes
+        // its messages visible in the order of their ids, whichever instance appends them.
+        sqlx::query!("SELECT pg_advisory_xact_lock($1)", room_id)
+            .execute(&mut *tx)
+            .await?;
+
+        let message = sqlx::query_as!(
+            TMessage,
+            "INSERT INTO chat_messages (room_id, user_name, message) VALUES ($1, $2, $3)
+            RETURNING seq id, user_name \"user\", message, created_at timestamp, edited_at",
+            room_id,
+            user,
+            message
+        )
+            .fetch_one(&mut *tx)
+            .await?;
+
+        sqlx::query!(
+            "DELETE FROM chat_messages
+            WHERE room_id = $1 AND seq <= (
+                SELECT seq FROM chat_messages WHERE room_id = $1 ORDER BY seq DESC OFFSET $2 LIMIT 1
+            )",
+            room_id,
+            self.size as i64
+        )
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(message)
+    }
+
+    async fn all(&self, room_id: i64) -> Result<Vec<TMessage>, AppError> {
+        let messages = sqlx::query_as!(
+            TMessage,
+            "SELECT seq id, user_name \"user\", message, created_at timestamp, edited_at
+            FROM chat_messages
+            WHERE room_id = $1
+            ORDER BY seq",
+            room_id
+        )
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(messages)
+    }
+
+    async fn page(&self, room_id: i64, before: Option<i64>, limit: i64) -> Result<Vec<TMessage>, AppError> {
+        let messages = sqlx::query_as!(
+            TMessage,
+            "SELECT seq id, user_name \"user\", message, created_at timestamp, edited_at
+            FROM chat_messages
+            WHERE room_id = $1 AND ($2::bigint IS NULL OR seq < $2)
+            ORDER BY seq DESC
+            LIMIT $3",
+            room_id,
+            before,
+            limit + 1
+        )
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(messages)
+    }
+
+    async fn edit(&self, room_id: i64, id: i64, user: &str, message: &str) -> Result<TMessage, AppError> {
+        self.check_author(room_id, id, user).await?;
+
+        let message = sqlx::query_as!(
+            TMessage,
+            "UPDATE chat_messages SET message = $4, edited_at = now()
+            WHERE room_id = $1 AND seq = $2 AND user_name = $3
+            RETURNING seq id, user_name \"user\", message, created_at timestamp, edited_at",
+            room_id,
+            id,
+            user,
+            message
+        )
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or_else(|| message_not_found(id))?;
+
+        Ok(message)
+    }
+
+    async fn delete(&self, room_id: i64, id: i64, user: &str) -> Result<(), AppError> {
+        self.check_author(room_id, id, user).await?;
+
+        sqlx::query!(
+            "DELETE FROM chat_messages WHERE room_id = $1 AND seq = $2 AND user_name = $3",
+            room_id,
+            id,
+            user
+        )
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::PgPool;
+
+    use crate::error::AppError;
+
+    use super::{ChatHistory, MemoryChatHistory, PgChatHistory};
+
+    /// Keeps three messages per room.
+    async fn check_bounded_history(history: &dyn ChatHistory) {
+        let mut ids = Vec::new();
+        for text in ["a", "b", "c", "d"] {
+            ids.push(history.append(1, "santa", text).await.unwrap().id);
+        }
+        history.append(2, "santa", "other room").await.unwrap();
+
+        let all = history.all(1).await.unwrap();
+        assert_eq!(all.iter().map(|message| message.id).collect::<Vec<_>>(), ids[1..]);
+        assert_eq!(all[0].message, "b");
+        assert_eq!(history.all(2).await.unwrap().len(), 1);
+
+        let page = history.page(1, Some(ids[3]), 1).await.unwrap();
+        assert_eq!(page.iter().map(|message| message.id).collect::<Vec<_>>(), [ids[2], ids[1]]);
+        let page = history.page(1, None, 10).await.unwrap();
+        assert_eq!(page.iter().map(|message| message.id).collect::<Vec<_>>(), [ids[3], ids[2], ids[1]]);
+        assert!(history.page(3, None, 10).await.unwrap().is_empty());
+    }
+
+    async fn check_edit_and_delete(history: &dyn ChatHistory) {
+        let sent = history.append(1, "santa", "ho").await.unwrap();
+
+        assert!(matches!(history.edit(1, sent.id, "grinch", "boo").await, Err(AppError::Forbidden(_))));
+        assert!(matches!(history.edit(1, sent.id + 1, "santa", "ho ho").await, Err(AppError::NotFound(_))));
+        assert!(matches!(history.edit(2, sent.id, "santa", "ho ho").await, Err(AppError::NotFound(_))));
+
+        let edited = history.edit(1, sent.id, "santa", "ho ho ho").await.unwrap();
+        assert_eq!(edited.message, "ho ho ho");
+        assert_eq!(edited.timestamp, sent.timestamp);
+        assert!(edited.edited_at.is_some());
+        assert_eq!(history.all(1).await.unwrap()[0].message, "ho ho ho");
+
+        assert!(matches!(history.delete(1, sent.id, "grinch").await, Err(AppError::Forbidden(_))));
+        assert!(matches!(history.delete(2, sent.id, "santa").await, Err(AppError::NotFound(_))));
+        history.delete(1, sent.id, "santa").await.unwrap();
+        assert!(history.all(1).await.unwrap().is_empty());
+        assert!(matches!(history.delete(1, sent.id, "santa").await, Err(AppError::NotFound(_))));
+    }
+
+    #[actix_web::test]
+    async fn test_bounded_history() {
+        check_bounded_history(&MemoryChatHistory::new(3)).await;
+    }
+
+    #[actix_web::test]
+    async fn test_edit_and_delete() {
+        check_edit_and_delete(&MemoryChatHistory::new(3)).await;
+    }
+
+    #[sqlx::test]
+    async fn test_pg_bounded_history(pool: PgPool) {
+        check_bounded_history(&PgChatHistory::new(pool, 3)).await;
+    }
+
+    #[sqlx::test]
+    async fn test_pg_edit_and_delete(pool: PgPool) {
+        check_edit_and_delete(&PgChatHistory::new(pool, 3)).await;
+    }
+
+    #[sqlx::test]
+    async fn test_pg_concurrent_appends(pool: PgPool) {
+        let history = PgChatHistory::new(pool, 5);
+
+        let appends = (0..20).map(|_| history.append(1, "santa", "ho"));
+        let mut ids: Vec<i64> = futures::future::try_join_all(appends)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|message| message.id)
+            .collect();
+
+        // the room keeps exactly the five latest messages
+        ids.sort();
+        let all = history.all(1).await.unwrap();
+        assert_eq!(all.iter().map(|message| message.id).collect::<Vec<_>>(), ids[15..]);
+    }
+}
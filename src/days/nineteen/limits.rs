@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use tokio::sync::Mutex;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::error::AppError;
+
+const DEFAULT_MAX_MESSAGE_LENGTH: usize = 128;
+const DEFAULT_MESSAGES_PER_SECOND: f64 = 5.0;
+const DEFAULT_MESSAGE_BURST: f64 = 10.0;
+const DEFAULT_MAX_ROOM_SIZE: usize = 100;
+
+/// How often buckets that filled up again are dropped, as they are no different from new ones.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// What a chat client is allowed to do.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatLimits {
+    /// In graphemes, so an emoji counts as one character however many bytes it takes.
+    pub max_message_length: usize,
+    /// The rate at which a user's frames, other than typing notifications, are accepted in the
+    /// long run.
+    pub messages_per_second: f64,
+    /// The number of such frames a user may send at once after being quiet for a while.
+    pub message_burst: f64,
+    /// The number of distinct users in a room.
+    pub max_room_size: usize,
+}
+
+impl Default for ChatLimits {
+    fn default() -> Self {
+        ChatLimits {
+            max_message_length: DEFAULT_MAX_MESSAGE_LENGTH,
+            messages_per_second: DEFAULT_MESSAGES_PER_SECOND,
+            message_burst: DEFAULT_MESSAGE_BURST,
+            max_room_size: DEFAULT_MAX_ROOM_SIZE,
+        }
+    }
+}
+
+/// The value of the environment variable, `default` when unset.
+pub(crate) fn env_or<T: std::str::FromStr>(name: &str, default: T) -> Result<T, AppError> {
+    match std::env::var(name) {
+        Ok(value) => value.parse().map_err(|_| AppError::Internal(format!("invalid {}: {}", name, value))),
+        Err(_) => Ok(default),
+    }
+}
+
+impl ChatLimits {
+    /// Reads `CHAT_MAX_MESSAGE_LENGTH`, `CHAT_MESSAGES_PER_SECOND`, `CHAT_MESSAGE_BURST` and
+    /// `CHAT_MAX_ROOM_SIZE`, falling back to the defaults for those unset. Fails on values that
+    /// cannot be parsed or that no one could chat with.
+    pub fn from_env() -> Result<Self, AppError> {
+        dotenv::dotenv().ok();
+
+        let limits = ChatLimits {
+            max_message_length: env_or("CHAT_MAX_MESSAGE_LENGTH", DEFAULT_MAX_MESSAGE_LENGTH)?,
+            messages_per_second: env_or("CHAT_MESSAGES_PER_SECOND", DEFAULT_MESSAGES_PER_SECOND)?,
+            message_burst: env_or("CHAT_MESSAGE_BURST", DEFAULT_MESSAGE_BURST)?,
+            max_room_size: env_or("CHAT_MAX_ROOM_SIZE", DEFAULT_MAX_ROOM_SIZE)?,
+        };
+        limits.validate()?;
+        Ok(limits)
+    }
+
+    /// Fails unless messages are let through at a positive rate and at least one at a time.
+    fn validate(&self) -> Result<(), AppError> {
+        // `is_finite` also rules out NaN
+        let valid_rate = self.messages_per_second.is_finite() && self.messages_per_second > 0.0;
+        if !valid_rate {
+            return Err(AppError::Internal(format!(
+                "messages per second must be positive, not {}",
+                self.messages_per_second
+            )));
+        }
+        let valid_burst = self.message_burst.is_finite() && self.message_burst >= 1.0;
+        if !valid_burst {
+            return Err(AppError::Internal(format!("message burst must be at least 1, not {}", self.message_burst)));
+        }
+        Ok(())
+    }
+
+    pub fn check_length(&self, message: &str) -> Result<(), AppError> {
+        let length = message.graphemes(true).count();
+        if length > self.max_message_length {
+            return Err(AppError::BadRequest(format!(
+                "message is {} characters long, at most {} are allowed",
+                length, self.max_message_length
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Debug)]
+struct Buckets {
+    by_user: HashMap<String, TokenBucket>,
+    swept: Instant,
+}
+
+/// Token buckets of the users, shared by all their connections.
+#[derive(Debug)]
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: &ChatLimits) -> Self {
+        RateLimiter {
+            rate: limits.messages_per_second,
+            burst: limits.message_burst.max(1.0),
+            buckets: Mutex::new(Buckets { by_user: HashMap::new(), swept: Instant::now() }),
+        }
+    }
+
+    /// Takes a token from the bucket of `user`, failing when it is empty.
+    pub async fn acquire(&self, user: &str) -> Result<(), AppError> {
+        self.acquire_at(user, Instant::now()).await
+    }
+
+    async fn acquire_at(&self, user: &str, now: Instant) -> Result<(), AppError> {
+        let mut buckets = self.buckets.lock().await;
+
+        if now.saturating_duration_since(buckets.swept) >= SWEEP_INTERVAL {
+            buckets.by_user.retain(|_, bucket| bucket.tokens + self.refill(bucket, now) < self.burst);
+            buckets.swept = now;
+        }
+
+        let bucket = buckets.by_user.entry(user.to_string()).or_insert(TokenBucket { tokens: self.burst, updated: now });
+        bucket.tokens = (bucket.tokens + self.refill(bucket, now)).min(self.burst);
+        bucket.updated = now;
+
+        if bucket.tokens < 1.0 {
+            return Err(AppError::TooManyRequests(format!(
+                "slow down, at most {} messages per second are allowed",
+                self.rate
+            )));
+        }
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+
+    fn refill(&self, bucket: &TokenBucket, now: Instant) -> f64 {
+        now.saturating_duration_since(bucket.updated).as_secs_f64() * self.rate
+    }
+
+    #[cfg(test)]
+    async fn len(&self) -> usize {
+        self.buckets.lock().await.by_user.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{ChatLimits, RateLimiter};
+
+    #[actix_web::test]
+    async fn test_message_length() {
+        let limits = ChatLimits { max_message_length: 3, ..ChatLimits::default() };
+
+        assert!(limits.check_length("abc").is_ok());
+        assert!(limits.check_length("🎄🎅🦌").is_ok());
+        // a flag is two code points but a single grapheme
+        assert!(limits.check_length("🇳🇴🇸🇪🇩🇰").is_ok());
+        assert!(limits.check_length("abcd").is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_token_bucket() {
+        let limiter = RateLimiter::new(&ChatLimits { messages_per_second: 2.0, message_burst: 3.0, ..ChatLimits::default() });
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            limiter.acquire_at("alice", start).await.unwrap();
+        }
+        assert!(limiter.acquire_at("alice", start).await.is_err());
+        limiter.acquire_at("bob", start).await.unwrap();
+
+        limiter.acquire_at("alice", start + Duration::from_millis(500)).await.unwrap();
+        assert!(limiter.acquire_at("alice", start + Duration::from_millis(600)).await.is_err());
+
+        // idle for long enough to fill up again, but not beyond the burst
+        let later = start + Duration::from_secs(60);
+        for _ in 0..3 {
+            limiter.acquire_at("alice", later).await.unwrap();
+        }
+        assert!(limiter.acquire_at("alice", later).await.is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_sweep() {
+        let limiter = RateLimiter::new(&ChatLimits { messages_per_second: 2.0, message_burst: 3.0, ..ChatLimits::default() });
+        let start = Instant::now();
+        limiter.acquire_at("alice", start).await.unwrap();
+        limiter.acquire_at("bob", start).await.unwrap();
+
+        // long since full again, but only dropped once the sweep is due
+        limiter.acquire_at("carol", start + Duration::from_secs(30)).await.unwrap();
+        assert_eq!(limiter.len().await, 3);
+
+        limiter.acquire_at("carol", start + Duration::from_secs(61)).await.unwrap();
+        assert_eq!(limiter.len().await, 1);
+    }
+
+    #[test]
+    fn test_invalid_limits() {
+        assert!(ChatLimits::default().validate().is_ok());
+        for messages_per_second in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(ChatLimits { messages_per_second, ..ChatLimits::default() }.validate().is_err());
+        }
+        for message_burst in [0.0, 0.5, -3.0, f64::NAN] {
+            assert!(ChatLimits { message_burst, ..ChatLimits::default() }.validate().is_err());
+        }
+    }
+}
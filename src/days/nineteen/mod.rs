@@ -1,29 +1,50 @@
-use std::collections::HashMap;
-use std::sync::atomic::AtomicI64;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
 
-use actix_web::{Error, get, HttpRequest, HttpResponse, post, web};
-use actix_ws::Message;
+use actix_web::{get, HttpRequest, HttpResponse, post, web};
+use actix_ws::{CloseCode, Message, Session};
+use chrono::{DateTime, Utc};
 use futures::StreamExt as _;
+use serde::{Deserialize, Serialize};
 use tokio::sync::broadcast::Sender;
-use tokio::sync::Mutex;
 
+use crate::error::AppError;
+use crate::migrations::RequireMigrations;
+use crate::pagination::{self, page_size, Page};
+
+use self::history::ChatHistory;
+use self::limits::{ChatLimits, RateLimiter};
+use self::rooms::{RoomEvent, Rooms};
+use self::stats::{ChatStats, Counter};
+
+pub mod history;
+pub mod limits;
+pub mod rooms;
+pub mod stats;
+
+// A dropped connection is only noticed when writing to it, so clients that stopped answering
+// pings are disconnected and leave their room.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Expects [`AppData`] and a [`ChatHistory`] to be registered as app data; they are built once in
+/// `main` so that connections to different workers share rooms, stats and rate limits.
 pub fn configure(cfg: &mut web::ServiceConfig) {
-    cfg.app_data(web::Data::new(AppData {
-        views: Default::default(),
-        rooms: Mutex::new(HashMap::new()),
-    }));
     cfg.service(ws);
     cfg.service(room_ws);
     cfg.service(reset);
     cfg.service(views);
+    cfg.service(room_stats);
+    cfg.service(room_history);
+    cfg.service(room_users);
 }
 
 #[get("/19/ws/ping")]
 async fn ws(
     req: HttpRequest,
     body: web::Payload,
-) -> Result<HttpResponse, Error> {
-    let (response, mut session, mut msg_stream) = actix_ws::handle(&req, body)?;
+) -> Result<HttpResponse, AppError> {
+    let (response, mut session, mut msg_stream) = actix_ws::handle(&req, body).map_err(AppError::bad_request)?;
 
     actix_rt::spawn(async move {
         let mut started = false;
@@ -47,79 +68,253 @@ async fn ws(
 }
 
 
+#[derive(Deserialize)]
+struct ResetParams {
+    room: Option<i64>,
+}
+
+/// Resets the counters of the given room, or of all rooms.
 #[post("/19/reset")]
-async fn reset(app_data: web::Data<AppData>) -> HttpResponse {
-    app_data.views.store(0, std::sync::atomic::Ordering::SeqCst);
+async fn reset(params: web::Query<ResetParams>, app_data: web::Data<AppData>) -> HttpResponse {
+    app_data.stats.reset(params.room).await;
     HttpResponse::Ok().finish()
 }
 
 #[get("/19/views")]
 async fn views(app_data: web::Data<AppData>) -> HttpResponse {
-    HttpResponse::Ok().json(app_data.views.load(std::sync::atomic::Ordering::SeqCst))
+    HttpResponse::Ok().json(app_data.stats.views().await)
 }
 
-#[derive(serde::Serialize, Clone, Debug)]
+/// The counters of all rooms together, and of every room and its users.
+#[get("/19/stats")]
+async fn room_stats(app_data: web::Data<AppData>) -> HttpResponse {
+    HttpResponse::Ok().json(app_data.stats.snapshot().await)
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct TMessage {
+    /// Assigned by the history, increasing with every message posted to the room.
+    id: i64,
     user: String,
     message: String,
+    timestamp: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    edited_at: Option<DateTime<Utc>>,
+}
+
+/// A frame sent by a client.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Envelope {
+    Message { message: String },
+    Dm { to: String, message: String },
+    Typing,
+    Edit { id: i64, message: String },
+    Delete { id: i64 },
+}
+
+impl Envelope {
+    /// Frames without a `type` are plain messages, as sent before envelopes were introduced.
+    fn parse(text: &str) -> Result<Self, AppError> {
+        let mut frame: serde_json::Value = serde_json::from_str(text).map_err(AppError::bad_request)?;
+        if let Some(fields) = frame.as_object_mut() {
+            fields.entry("type").or_insert_with(|| "message".into());
+        }
+        serde_json::from_value(frame).map_err(AppError::bad_request)
+    }
 }
 
-#[derive(serde::Deserialize)]
-struct SentMessage {
+/// Sent back to a client whose frame could not be handled.
+#[derive(Serialize)]
+#[serde(tag = "type", rename = "error")]
+struct ErrorFrame {
     message: String,
 }
 
+impl From<AppError> for ErrorFrame {
+    fn from(err: AppError) -> Self {
+        ErrorFrame { message: err.client_message() }
+    }
+}
+
+/// Handles a frame of `user`, broadcasting its outcome to the room.
+async fn handle_envelope(
+    envelope: Envelope,
+    room_id: i64,
+    user: &str,
+    sender: &Sender<RoomEvent>,
+    history: &dyn ChatHistory,
+    app_data: &AppData,
+) -> Result<(), AppError> {
+    // typing frames are neither stored nor counted, so they do not use up the user's messages
+    if !matches!(envelope, Envelope::Typing) {
+        app_data.limiter.acquire(user).await?;
+    }
+    if let Envelope::Message { message } | Envelope::Dm { message, .. } | Envelope::Edit { message, .. } = &envelope {
+        app_data.limits.check_length(message)?;
+    }
+
+    // held until the message is broadcast below
+    let mut _posting = None;
+    let event = match envelope {
+        Envelope::Message { message } => {
+            _posting = Some(app_data.rooms.lock_posting(room_id).await);
+            RoomEvent::Message(history.append(room_id, user, &message).await?)
+        }
+        Envelope::Dm { to, message } => {
+            if !app_data.rooms.contains(room_id, &to).await {
+                return Err(AppError::NotFound(format!("{} is not in room {}", to, room_id)));
+            }
+            RoomEvent::Dm { from: user.to_string(), to, message, timestamp: Utc::now() }
+        }
+        Envelope::Typing => RoomEvent::Typing { user: user.to_string() },
+        Envelope::Edit { id, message } => RoomEvent::Edit(history.edit(room_id, id, user, &message).await?),
+        Envelope::Delete { id } => {
+            history.delete(room_id, id, user).await?;
+            RoomEvent::Delete { id, user: user.to_string() }
+        }
+    };
+
+    let posted = matches!(event, RoomEvent::Message(_));
+    let _ = sender.send(event);
+    if posted {
+        app_data.stats.record(room_id, user, Counter::Message).await;
+    }
+    Ok(())
+}
+
+async fn send_event(session: &mut Session, event: &impl Serialize) -> Result<(), actix_ws::Closed> {
+    session.text(serde_json::to_string(event).unwrap()).await
+}
+
 #[derive(Debug)]
 pub struct AppData {
-    pub views: AtomicI64,
-    pub rooms: Mutex<HashMap<i64, Sender<TMessage>>>,
+    pub stats: ChatStats,
+    pub rooms: Rooms,
+    pub limits: ChatLimits,
+    pub limiter: RateLimiter,
 }
 
-#[get("/19/ws/room/{number}/user/{user}")]
+impl AppData {
+    pub fn new(limits: ChatLimits) -> Self {
+        AppData {
+            stats: ChatStats::default(),
+            rooms: Rooms::default(),
+            limiter: RateLimiter::new(&limits),
+            limits,
+        }
+    }
+}
+
+impl Default for AppData {
+    fn default() -> Self {
+        AppData::new(ChatLimits::default())
+    }
+}
+
+/// The users connected to a room, sorted by name.
+#[get("/19/room/{id}/users")]
+async fn room_users(room_id: web::Path<i64>, app_data: web::Data<AppData>) -> HttpResponse {
+    HttpResponse::Ok().json(app_data.rooms.members(room_id.into_inner()).await)
+}
+
+#[derive(Deserialize)]
+struct HistoryParams {
+    after: Option<String>,
+    limit: Option<i64>,
+}
+
+/// The messages of a room, newest first.
+#[get("/19/room/{id}/history", wrap = "RequireMigrations")]
+async fn room_history(
+    room_id: web::Path<i64>,
+    params: web::Query<HistoryParams>,
+    history: web::Data<dyn ChatHistory>,
+) -> Result<HttpResponse, AppError> {
+    let limit = page_size(params.limit)?;
+    let before = params.after
+        .as_deref()
+        .map(pagination::decode_cursor::<TMessage>)
+        .transpose()?
+        .map(|message| message.id);
+
+    let messages = history.page(room_id.into_inner(), before, limit).await?;
+
+    Ok(HttpResponse::Ok().json(Page::new(messages, limit)?))
+}
+
+#[get("/19/ws/room/{number}/user/{user}", wrap = "RequireMigrations")]
 async fn room_ws(
     req: HttpRequest,
     body: web::Payload,
     path: web::Path<(i64, String)>,
     app_data: web::Data<AppData>,
+    history: web::Data<dyn ChatHistory>,
 )
-    -> Result<HttpResponse, Error> {
+    -> Result<HttpResponse, AppError> {
     let (room_id, username) = path.into_inner();
-    let mut rooms = app_data.rooms.lock().await;
-    let sender = match rooms.get(&room_id) {
-        Some(s) => s.clone(),
-        None => {
-            let sender = Sender::new(1024);
-            let sender_clone = sender.clone();
-            rooms.insert(room_id, sender);
-            sender_clone
+
+    let (response, og_session, mut msg_stream) = actix_ws::handle(&req, body).map_err(AppError::bad_request)?;
+
+    // Joined only once the handshake succeeded, as only then will the connection be left again.
+    let (sender, mut rx) = match app_data.rooms.join(room_id, &username, app_data.limits.max_room_size).await {
+        Ok(joined) => joined,
+        Err(err) => {
+            let mut session = og_session;
+            actix_web::rt::spawn(async move {
+                let _ = send_event(&mut session, &ErrorFrame::from(err)).await;
+                let _ = session.close(Some(CloseCode::Policy.into())).await;
+            });
+            return Ok(response);
         }
     };
-    drop(rooms);
-    let mut rx = sender.subscribe();
-
-    let (response, og_session, mut msg_stream) = actix_ws::handle(&req, body)?;
+    app_data.stats.record(room_id, &username, Counter::Connection).await;
 
     let mut session = og_session.clone();
+    let tx_history = history.clone();
+    let tx_username = username.clone();
+    let rx_data = app_data.clone();
+    let member_data = app_data.clone();
+    let member = username.clone();
     actix_web::rt::spawn(async move {
         let mut rx_task = actix_web::rt::spawn(async move {
-            while let Some(Ok(msg)) = msg_stream.recv().await {
-                match msg {
-                    Message::Ping(bytes) => {
-                        if session.pong(&bytes).await.is_err() {
-                            return;
-                        }
-                    }
-                    Message::Text(msg) => {
-                        if let Ok(tweet) = serde_json::from_str::<SentMessage>(&msg) {
-                            if tweet.message.len() <= 128 {
-                                let _ = sender.send(TMessage { user: username.clone(), message: tweet.message });
+            let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+            let mut last_seen = Instant::now();
+            loop {
+                tokio::select! {
+                    msg = msg_stream.recv() => {
+                        let Some(Ok(msg)) = msg else {
+                            break;
+                        };
+                        last_seen = Instant::now();
+                        match msg {
+                            Message::Ping(bytes) if session.pong(&bytes).await.is_err() => {
+                                return;
+                            }
+                            Message::Text(msg) => {
+                                let handled = match Envelope::parse(&msg) {
+                                    Ok(envelope) => {
+                                        handle_envelope(envelope, room_id, &username, &sender, history.as_ref(), &rx_data).await
+                                    }
+                                    Err(err) => Err(err),
+                                };
+                                if let Err(err) = handled {
+                                    if send_event(&mut session, &ErrorFrame::from(err)).await.is_err() {
+                                        return;
+                                    }
+                                }
+                            }
+                            Message::Close(_) => {
+                                break;
                             }
+                            _ => {}
                         }
                     }
-                    Message::Close(_) => {
-                        break;
+                    _ = heartbeat.tick() => {
+                        if last_seen.elapsed() > CLIENT_TIMEOUT || session.ping(b"").await.is_err() {
+                            break;
+                        }
                     }
-                    _ => {}
                 }
             }
 
@@ -128,11 +323,26 @@ async fn room_ws(
 
         let mut session = og_session.clone();
         let mut tx_task = actix_web::rt::spawn(async move {
-            while let Ok(tweet) = rx.recv().await {
-                if session.text(serde_json::to_string(&tweet).unwrap()).await.is_err() {
+            // Subscribed before reading the history, so live messages it already holds are skipped.
+            let mut replayed = HashSet::new();
+            for message in tx_history.all(room_id).await.unwrap_or_default() {
+                replayed.insert(message.id);
+                if send_event(&mut session, &RoomEvent::Message(message)).await.is_err() {
+                    return;
+                }
+            }
+
+            while let Ok(event) = rx.recv().await {
+                match &event {
+                    RoomEvent::Message(message) if replayed.remove(&message.id) => continue,
+                    _ if !event.is_for(&tx_username) => continue,
+                    _ => {}
+                }
+                if send_event(&mut session, &event).await.is_err() {
                     return;
-                } else {
-                    app_data.views.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                }
+                if let RoomEvent::Message(_) = event {
+                    app_data.stats.record(room_id, &tx_username, Counter::View).await;
                 }
             }
         });
@@ -141,7 +351,260 @@ async fn room_ws(
             _ = (&mut tx_task) => rx_task.abort(),
             _ = (&mut rx_task) => tx_task.abort()
         }
+
+        member_data.rooms.leave(room_id, &member).await;
     });
 
     Ok(response)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::pin::Pin;
+    use std::sync::Arc;
+
+    use actix_web::{body, dev, test, web, App};
+    use actix_web::http::{header, StatusCode};
+    use futures::{stream, Stream};
+    use serde_json::{json, Value};
+
+    use crate::error::AppError;
+
+    use super::history::{ChatHistory, MemoryChatHistory};
+    use super::limits::ChatLimits;
+    use super::rooms::RoomEvent;
+    use super::stats::Counter;
+    use super::{handle_envelope, AppData, Envelope};
+
+    #[actix_web::test]
+    async fn test_room_history() {
+        let history: Arc<dyn ChatHistory> = Arc::new(MemoryChatHistory::new(100));
+        for text in ["first", "second", "third"] {
+            history.append(7, "elf", text).await.unwrap();
+        }
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(AppData::default()))
+                .app_data(web::Data::from(history))
+                .configure(super::configure)
+        ).await;
+
+        let req = test::TestRequest::get().uri("/19/room/7/history?limit=2").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+        let page: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(page["items"][0]["id"], 3);
+        assert_eq!(page["items"][0]["message"], "third");
+        assert!(page["items"][0]["timestamp"].is_string());
+        assert_eq!(page["items"][1]["id"], 2);
+        assert_eq!(page["items"].as_array().unwrap().len(), 2);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/19/room/7/history?limit=2&after={}", page["next"].as_str().unwrap()))
+            .to_request();
+        let bytes = body::to_bytes(test::call_service(&app, req).await.into_body()).await.unwrap();
+        let page: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(page["items"][0]["id"], 1);
+        assert_eq!(page["items"][0]["user"], "elf");
+        assert_eq!(page["next"], Value::Null);
+
+        let req = test::TestRequest::get().uri("/19/room/7/history?limit=0").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_room_users() {
+        let app_data = web::Data::new(AppData::default());
+        app_data.rooms.join(3, "rudolph", 10).await.unwrap();
+        app_data.rooms.join(3, "dasher", 10).await.unwrap();
+
+        let history: Arc<dyn ChatHistory> = Arc::new(MemoryChatHistory::new(100));
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(history))
+                .app_data(app_data.clone())
+                .configure(super::configure)
+        ).await;
+
+        let req = test::TestRequest::get().uri("/19/room/3/users").to_request();
+        let bytes = body::to_bytes(test::call_service(&app, req).await.into_body()).await.unwrap();
+        assert_eq!(serde_json::from_slice::<Value>(&bytes).unwrap(), json!(["dasher", "rudolph"]));
+
+        app_data.rooms.leave(3, "dasher").await;
+        app_data.rooms.leave(3, "rudolph").await;
+
+        let req = test::TestRequest::get().uri("/19/room/3/users").to_request();
+        let bytes = body::to_bytes(test::call_service(&app, req).await.into_body()).await.unwrap();
+        assert_eq!(serde_json::from_slice::<Value>(&bytes).unwrap(), json!([]));
+    }
+
+    #[actix_web::test]
+    async fn test_stats() {
+        let app_data = web::Data::new(AppData::default());
+        app_data.stats.record(1, "elf", Counter::Message).await;
+        app_data.stats.record(1, "santa", Counter::View).await;
+        app_data.stats.record(2, "santa", Counter::View).await;
+
+        let history: Arc<dyn ChatHistory> = Arc::new(MemoryChatHistory::new(100));
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(history))
+                .app_data(app_data.clone())
+                .configure(super::configure)
+        ).await;
+
+        let req = test::TestRequest::get().uri("/19/stats").to_request();
+        let bytes = body::to_bytes(test::call_service(&app, req).await.into_body()).await.unwrap();
+        let stats: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(stats["views"], 2);
+        assert_eq!(stats["messages"], 1);
+        assert_eq!(stats["rooms"]["1"]["users"]["elf"], json!({"views": 0, "messages": 1, "connections": 0}));
+        assert_eq!(stats["rooms"]["2"]["views"], 1);
+
+        let req = test::TestRequest::post().uri("/19/reset?room=1").to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+
+        let req = test::TestRequest::get().uri("/19/views").to_request();
+        let bytes = body::to_bytes(test::call_service(&app, req).await.into_body()).await.unwrap();
+        assert_eq!(serde_json::from_slice::<Value>(&bytes).unwrap(), json!(1));
+
+        let req = test::TestRequest::post().uri("/19/reset").to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::get().uri("/19/stats").to_request();
+        let bytes = body::to_bytes(test::call_service(&app, req).await.into_body()).await.unwrap();
+        assert_eq!(
+            serde_json::from_slice::<Value>(&bytes).unwrap(),
+            json!({"views": 0, "messages": 0, "connections": 0, "rooms": {}})
+        );
+    }
+
+    fn handshake(room_id: i64, user: &str) -> test::TestRequest {
+        test::TestRequest::get()
+            .uri(&format!("/19/ws/room/{}/user/{}", room_id, user))
+            .insert_header((header::CONNECTION, "upgrade"))
+            .insert_header((header::UPGRADE, "websocket"))
+            .insert_header((header::SEC_WEBSOCKET_VERSION, "13"))
+            .insert_header((header::SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ=="))
+    }
+
+    fn endless_payload() -> dev::Payload {
+        dev::Payload::from(Box::pin(stream::pending()) as Pin<Box<dyn Stream<Item = _>>>)
+    }
+
+    #[actix_web::test]
+    async fn test_shared_between_workers() {
+        // every worker builds its own `App`, but they all get the `AppData` built once in `main`
+        let app_data = web::Data::new(AppData::default());
+        let history: Arc<dyn ChatHistory> = Arc::new(MemoryChatHistory::new(100));
+        let mut workers = Vec::new();
+        for _ in 0..2 {
+            let app = App::new()
+                .app_data(app_data.clone())
+                .app_data(web::Data::from(history.clone()))
+                .configure(super::configure);
+            workers.push(test::init_service(app).await);
+        }
+
+        for (worker, user) in workers.iter().zip(["alice", "bob"]) {
+            // a payload that never ends keeps the connection, and so the user, in the room
+            let (req, _) = handshake(1, user).to_request().replace_payload(endless_payload());
+            let resp = test::call_service(worker, req).await;
+            assert_eq!(resp.status(), StatusCode::SWITCHING_PROTOCOLS);
+        }
+
+        for worker in &workers {
+            let req = test::TestRequest::get().uri("/19/stats").to_request();
+            let bytes = body::to_bytes(test::call_service(worker, req).await.into_body()).await.unwrap();
+            let stats: Value = serde_json::from_slice(&bytes).unwrap();
+            assert_eq!(stats["connections"], 2);
+            assert_eq!(stats["rooms"]["1"]["users"]["bob"]["connections"], 1);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_presence_shared_between_workers() {
+        let app_data = web::Data::new(AppData::default());
+        let history: Arc<dyn ChatHistory> = Arc::new(MemoryChatHistory::new(100));
+        let mut workers = Vec::new();
+        for _ in 0..2 {
+            let app = App::new()
+                .app_data(app_data.clone())
+                .app_data(web::Data::from(history.clone()))
+                .configure(super::configure);
+            workers.push(test::init_service(app).await);
+        }
+
+        let (_, mut events) = app_data.rooms.join(2, "carol", 10).await.unwrap();
+        assert_eq!(events.recv().await.unwrap(), RoomEvent::Join { user: "carol".to_string() });
+
+        // the responses carry what is sent to the clients, so the connections stay open as long as they are kept
+        let mut connections = Vec::new();
+        for (worker, user) in workers.iter().zip(["alice", "bob"]) {
+            let (req, _) = handshake(2, user).to_request().replace_payload(endless_payload());
+            connections.push(test::call_service(worker, req).await);
+            assert_eq!(events.recv().await.unwrap(), RoomEvent::Join { user: user.to_string() });
+        }
+
+        for worker in &workers {
+            let req = test::TestRequest::get().uri("/19/room/2/users").to_request();
+            let bytes = body::to_bytes(test::call_service(worker, req).await.into_body()).await.unwrap();
+            assert_eq!(serde_json::from_slice::<Value>(&bytes).unwrap(), json!(["alice", "bob", "carol"]));
+        }
+
+        // without a payload the connection closes straight away, leaving the room again
+        test::call_service(&workers[1], handshake(2, "dasher").to_request()).await;
+        assert_eq!(events.recv().await.unwrap(), RoomEvent::Join { user: "dasher".to_string() });
+        assert_eq!(events.recv().await.unwrap(), RoomEvent::Leave { user: "dasher".to_string() });
+        assert_eq!(app_data.rooms.members(2).await, ["alice", "bob", "carol"]);
+    }
+
+    #[actix_web::test]
+    async fn test_envelopes() {
+        assert!(matches!(
+            Envelope::parse(r#"{"message": "hello"}"#),
+            Ok(Envelope::Message { message }) if message == "hello"
+        ));
+        assert!(matches!(
+            Envelope::parse(r#"{"type": "dm", "to": "bob", "message": "psst"}"#),
+            Ok(Envelope::Dm { to, .. }) if to == "bob"
+        ));
+        assert!(matches!(Envelope::parse(r#"{"type": "typing"}"#), Ok(Envelope::Typing)));
+        assert!(matches!(Envelope::parse(r#"{"type": "edit", "id": 3, "message": "fixed"}"#), Ok(Envelope::Edit { id: 3, .. })));
+        assert!(matches!(Envelope::parse(r#"{"type": "delete", "id": 3}"#), Ok(Envelope::Delete { id: 3 })));
+
+        for frame in [r#"{"type": "shout", "message": "hi"}"#, r#"{"type": "edit", "message": "no id"}"#, "hello", "[]"] {
+            assert!(Envelope::parse(frame).is_err(), "{}", frame);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_typing_not_rate_limited() {
+        let limits = ChatLimits { messages_per_second: 0.001, message_burst: 1.0, ..ChatLimits::default() };
+        let app_data = AppData::new(limits);
+        let history = MemoryChatHistory::new(100);
+        let (sender, mut events) = tokio::sync::broadcast::channel(16);
+        let send = |envelope| handle_envelope(envelope, 1, "alice", &sender, &history, &app_data);
+
+        for _ in 0..3 {
+            send(Envelope::Typing).await.unwrap();
+        }
+        send(Envelope::Message { message: "hi".to_string() }).await.unwrap();
+        // out of messages, but still typing
+        send(Envelope::Typing).await.unwrap();
+        assert!(matches!(
+            send(Envelope::Message { message: "hi again".to_string() }).await,
+            Err(AppError::TooManyRequests(_))
+        ));
+
+        let mut typing = 0;
+        while let Ok(event) = events.try_recv() {
+            typing += matches!(event, RoomEvent::Typing { .. }) as usize;
+        }
+        assert_eq!(typing, 4);
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::broadcast::{Receiver, Sender};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+use crate::error::AppError;
+
+use super::TMessage;
+
+const ROOM_CAPACITY: usize = 1024;
+
+/// What is broadcast to the connections of a room, as sent to the clients.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum RoomEvent {
+    Message(TMessage),
+    /// Only delivered to the connections of the sender and the recipient.
+    Dm {
+        from: String,
+        to: String,
+        message: String,
+        timestamp: DateTime<Utc>,
+    },
+    /// Not delivered back to the typing user.
+    Typing { user: String },
+    Edit(TMessage),
+    Delete { id: i64, user: String },
+    /// The user's first connection to the room opened.
+    Join { user: String },
+    /// The user's last connection to the room closed.
+    Leave { user: String },
+}
+
+impl RoomEvent {
+    /// Whether the event is delivered to the connections of `user`.
+    pub fn is_for(&self, user: &str) -> bool {
+        match self {
+            RoomEvent::Dm { from, to, .. } => from == user || to == user,
+            RoomEvent::Typing { user: typing } => typing != user,
+            _ => true,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Room {
+    sender: Sender<RoomEvent>,
+    /// The number of open connections of every member.
+    members: BTreeMap<String, usize>,
+    /// Held from storing a message until it is broadcast, so that messages are broadcast in the
+    /// order of their ids.
+    posting: Arc<Mutex<()>>,
+}
+
+/// The rooms with at least one open connection.
+#[derive(Debug, Default)]
+pub struct Rooms {
+    rooms: Mutex<HashMap<i64, Room>>,
+}
+
+impl Rooms {
+    /// Adds a connection of `user` to the room, creating the room if needed, and announces the
+    /// user unless they were already connected. The receiver gets the announcement too.
+    ///
+    /// Fails when `user` would be one member more than `max_members`; further connections of
+    /// members are always accepted.
+    pub async fn join(
+        &self,
+        room_id: i64,
+        user: &str,
+        max_members: usize,
+    ) -> Result<(Sender<RoomEvent>, Receiver<RoomEvent>), AppError> {
+        let mut rooms = self.rooms.lock().await;
+        let (is_member, members) = rooms
+            .get(&room_id)
+            .map_or((false, 0), |room| (room.members.contains_key(user), room.members.len()));
+        if !is_member && members >= max_members {
+            return Err(AppError::Conflict(format!("room {} is full", room_id)));
+        }
+        let room = rooms.entry(room_id).or_insert_with(|| Room {
+            sender: Sender::new(ROOM_CAPACITY),
+            members: BTreeMap::new(),
+            posting: Arc::default(),
+        });
+
+        let receiver = room.sender.subscribe();
+        let connections = room.members.entry(user.to_string()).or_default();
+        *connections += 1;
+        if *connections == 1 {
+            let _ = room.sender.send(RoomEvent::Join { user: user.to_string() });
+        }
+        Ok((room.sender.clone(), receiver))
+    }
+
+    /// Removes a connection of `user` from the room, announcing the user's departure when it was
+    /// their last one and forgetting the room once it is empty.
+    pub async fn leave(&self, room_id: i64, user: &str) {
+        let mut rooms = self.rooms.lock().await;
+        let Some(room) = rooms.get_mut(&room_id) else {
+            return;
+        };
+        let Some(connections) = room.members.get_mut(user) else {
+            return;
+        };
+
+        *connections -= 1;
+        if *connections == 0 {
+            room.members.remove(user);
+            let _ = room.sender.send(RoomEvent::Leave { user: user.to_string() });
+        }
+        if room.members.is_empty() {
+            rooms.remove(&room_id);
+        }
+    }
+
+    pub async fn contains(&self, room_id: i64, user: &str) -> bool {
+        let rooms = self.rooms.lock().await;
+        rooms.get(&room_id).is_some_and(|room| room.members.contains_key(user))
+    }
+
+    /// Waits until no other message is being posted to the room, then keeps others waiting until
+    /// the guard is dropped.
+    pub async fn lock_posting(&self, room_id: i64) -> OwnedMutexGuard<()> {
+        let posting = match self.rooms.lock().await.get(&room_id) {
+            Some(room) => room.posting.clone(),
+            // nobody is connected to receive the message, so there is nothing to keep in order
+            None => Arc::default(),
+        };
+        posting.lock_owned().await
+    }
+
+    /// The members of the room, sorted by name.
+    pub async fn members(&self, room_id: i64) -> Vec<String> {
+        let rooms = self.rooms.lock().await;
+        rooms.get(&room_id).map(|room| room.members.keys().cloned().collect()).unwrap_or_default()
+    }
+
+    #[cfg(test)]
+    async fn len(&self) -> usize {
+        self.rooms.lock().await.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::Utc;
+
+    use super::{RoomEvent, Rooms};
+
+    #[actix_web::test]
+    async fn test_presence() {
+        let rooms = Rooms::default();
+
+        let (_, mut alice) = rooms.join(1, "alice", 10).await.unwrap();
+        assert_eq!(alice.recv().await.unwrap(), RoomEvent::Join { user: "alice".to_string() });
+
+        rooms.join(1, "bob", 10).await.unwrap();
+        rooms.join(1, "bob", 10).await.unwrap();
+        assert_eq!(alice.recv().await.unwrap(), RoomEvent::Join { user: "bob".to_string() });
+        assert_eq!(rooms.members(1).await, ["alice", "bob"]);
+        assert!(rooms.contains(1, "bob").await);
+        assert!(!rooms.contains(2, "bob").await);
+
+        // bob still has a connection open
+        rooms.leave(1, "bob").await;
+        assert_eq!(rooms.members(1).await, ["alice", "bob"]);
+
+        rooms.leave(1, "bob").await;
+        assert_eq!(alice.recv().await.unwrap(), RoomEvent::Leave { user: "bob".to_string() });
+        assert!(alice.try_recv().is_err());
+
+        rooms.leave(1, "alice").await;
+        assert!(rooms.members(1).await.is_empty());
+        assert_eq!(rooms.len().await, 0);
+    }
+
+    #[actix_web::test]
+    async fn test_room_size() {
+        let rooms = Rooms::default();
+        rooms.join(1, "alice", 2).await.unwrap();
+        rooms.join(1, "bob", 2).await.unwrap();
+
+        assert!(rooms.join(1, "carol", 2).await.is_err());
+        // another connection of a member does not make the room any bigger
+        rooms.join(1, "bob", 2).await.unwrap();
+        rooms.join(2, "carol", 2).await.unwrap();
+
+        rooms.leave(1, "alice").await;
+        rooms.join(1, "carol", 2).await.unwrap();
+        assert_eq!(rooms.members(1).await, ["bob", "carol"]);
+
+        // not even the first user gets into a room of size zero
+        assert!(rooms.join(3, "dasher", 0).await.is_err());
+        assert_eq!(rooms.len().await, 2);
+    }
+
+    #[actix_web::test]
+    async fn test_posting_lock() {
+        let rooms = Rooms::default();
+        rooms.join(1, "alice", 10).await.unwrap();
+        rooms.join(2, "alice", 10).await.unwrap();
+
+        let posting = rooms.lock_posting(1).await;
+        assert!(tokio::time::timeout(Duration::from_millis(50), rooms.lock_posting(1)).await.is_err());
+        // other rooms are not held up
+        drop(rooms.lock_posting(2).await);
+
+        drop(posting);
+        drop(rooms.lock_posting(1).await);
+    }
+
+    #[test]
+    fn test_delivery() {
+        let dm = RoomEvent::Dm {
+            from: "alice".to_string(),
+            to: "bob".to_string(),
+            message: "psst".to_string(),
+            timestamp: Utc::now(),
+        };
+        assert!(dm.is_for("alice"));
+        assert!(dm.is_for("bob"));
+        assert!(!dm.is_for("carol"));
+
+        let typing = RoomEvent::Typing { user: "alice".to_string() };
+        assert!(!typing.is_for("alice"));
+        assert!(typing.is_for("bob"));
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use tokio::sync::Mutex;
+
+/// How many rooms, and users in each room, get counters of their own. Room ids and user names
+/// come from the URL, so anyone could otherwise grow the stats without bound.
+const MAX_ROOMS: usize = 1000;
+const MAX_USERS_PER_ROOM: usize = 100;
+
+#[derive(Serialize, Default, Clone, Copy, Debug, PartialEq)]
+pub struct Counters {
+    /// Messages delivered to the user's connections.
+    pub views: i64,
+    /// Messages the user posted.
+    pub messages: i64,
+    /// Connections the user opened.
+    pub connections: i64,
+}
+
+impl Counters {
+    fn add(&mut self, counter: Counter) {
+        match counter {
+            Counter::View => self.views += 1,
+            Counter::Message => self.messages += 1,
+            Counter::Connection => self.connections += 1,
+        }
+    }
+
+    fn add_all(&mut self, other: &Counters) {
+        self.views += other.views;
+        self.messages += other.messages;
+        self.connections += other.connections;
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Counter {
+    View,
+    Message,
+    Connection,
+}
+
+#[derive(Serialize, Default, Clone, Debug, PartialEq)]
+pub struct RoomStats {
+    #[serde(flatten)]
+    pub totals: Counters,
+    pub users: BTreeMap<String, Counters>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Stats {
+    #[serde(flatten)]
+    pub totals: Counters,
+    pub rooms: BTreeMap<i64, RoomStats>,
+}
+
+#[derive(Debug, Default)]
+struct Tracked {
+    rooms: BTreeMap<i64, RoomStats>,
+    /// The traffic of the rooms beyond [`MAX_ROOMS`].
+    untracked: Counters,
+}
+
+/// The traffic of every room since it was last reset. The totals count everything, but only the
+/// first [`MAX_ROOMS`] rooms and [`MAX_USERS_PER_ROOM`] users of each are broken down.
+#[derive(Debug, Default)]
+pub struct ChatStats {
+    tracked: Mutex<Tracked>,
+}
+
+impl ChatStats {
+    pub async fn record(&self, room_id: i64, user: &str, counter: Counter) {
+        let mut tracked = self.tracked.lock().await;
+        let Tracked { rooms, untracked } = &mut *tracked;
+        if !rooms.contains_key(&room_id) && rooms.len() >= MAX_ROOMS {
+            untracked.add(counter);
+            return;
+        }
+
+        let room = rooms.entry(room_id).or_default();
+        room.totals.add(counter);
+        if room.users.contains_key(user) || room.users.len() < MAX_USERS_PER_ROOM {
+            room.users.entry(user.to_string()).or_default().add(counter);
+        }
+    }
+
+    /// The views of all rooms.
+    pub async fn views(&self) -> i64 {
+        let tracked = self.tracked.lock().await;
+        tracked.untracked.views + tracked.rooms.values().map(|room| room.totals.views).sum::<i64>()
+    }
+
+    pub async fn snapshot(&self) -> Stats {
+        let tracked = self.tracked.lock().await;
+        let mut totals = tracked.untracked;
+        for room in tracked.rooms.values() {
+            totals.add_all(&room.totals);
+        }
+        Stats { totals, rooms: tracked.rooms.clone() }
+    }
+
+    /// Forgets the counters of a single room, or of all of them. The traffic of untracked rooms
+    /// is only forgotten with all of them.
+    pub async fn reset(&self, room_id: Option<i64>) {
+        let mut tracked = self.tracked.lock().await;
+        match room_id {
+            Some(room_id) => {
+                tracked.rooms.remove(&room_id);
+            }
+            None => *tracked = Tracked::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChatStats, Counter, Counters, MAX_ROOMS, MAX_USERS_PER_ROOM};
+
+    #[actix_web::test]
+    async fn test_counters() {
+        let stats = ChatStats::default();
+        stats.record(1, "alice", Counter::Connection).await;
+        stats.record(1, "alice", Counter::Message).await;
+        stats.record(1, "alice", Counter::View).await;
+        stats.record(1, "bob", Counter::View).await;
+        stats.record(2, "bob", Counter::View).await;
+
+        assert_eq!(stats.views().await, 3);
+
+        let snapshot = stats.snapshot().await;
+        assert_eq!(snapshot.totals, Counters { views: 3, messages: 1, connections: 1 });
+        assert_eq!(snapshot.rooms[&1].totals, Counters { views: 2, messages: 1, connections: 1 });
+        assert_eq!(snapshot.rooms[&1].users["bob"], Counters { views: 1, messages: 0, connections: 0 });
+
+        stats.reset(Some(1)).await;
+        assert_eq!(stats.views().await, 1);
+        assert!(!stats.snapshot().await.rooms.contains_key(&1));
+
+        stats.reset(None).await;
+        assert!(stats.snapshot().await.rooms.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_bounded() {
+        let stats = ChatStats::default();
+        for room_id in 0..MAX_ROOMS as i64 + 10 {
+            stats.record(room_id, "alice", Counter::View).await;
+        }
+        for user in 0..MAX_USERS_PER_ROOM + 10 {
+            stats.record(0, &user.to_string(), Counter::View).await;
+        }
+        stats.record(0, "alice", Counter::Message).await;
+
+        let snapshot = stats.snapshot().await;
+        assert_eq!(snapshot.rooms.len(), MAX_ROOMS);
+        assert_eq!(snapshot.rooms[&0].users.len(), MAX_USERS_PER_ROOM);
+        assert_eq!(snapshot.rooms[&0].users["alice"], Counters { views: 1, messages: 1, connections: 0 });
+
+        // the totals still count everything
+        let views = (MAX_ROOMS + 10 + MAX_USERS_PER_ROOM + 10) as i64;
+        assert_eq!(snapshot.rooms[&0].totals.views, MAX_USERS_PER_ROOM as i64 + 11);
+        assert_eq!(snapshot.totals.views, views);
+        assert_eq!(stats.views().await, views);
+
+        stats.reset(None).await;
+        assert_eq!(stats.views().await, 0);
+    }
+}
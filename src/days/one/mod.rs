@@ -1,17 +1,23 @@
-use actix_web::{get, web, HttpResponse, Responder};
+use actix_web::{get, web, HttpResponse};
+
+use crate::error::AppError;
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(part_1_and_2);
 }
 
 #[get("/1/{nums}*")]
-async fn part_1_and_2(nums: web::Path<String>) -> impl Responder {
+async fn part_1_and_2(nums: web::Path<String>) -> Result<HttpResponse, AppError> {
     let nums = nums
         .split('/')
-        .map(|s| s.parse::<i32>().unwrap())
-        .collect::<Vec<_>>();
-    let result = nums.iter().fold(0, |acc, &num| acc ^ num).pow(3);
-    HttpResponse::Ok().body(format!("{}", result))
+        .map(|s| s.parse::<i32>().map_err(|_| AppError::BadRequest(format!("invalid number: {}", s))))
+        .collect::<Result<Vec<_>, _>>()?;
+    let result = nums
+        .iter()
+        .fold(0, |acc, &num| acc ^ num)
+        .checked_pow(3)
+        .ok_or_else(|| AppError::bad_request("result overflows"))?;
+    Ok(HttpResponse::Ok().body(format!("{}", result)))
 }
 
 #[cfg(test)]
@@ -55,4 +61,13 @@ mod tests {
         let bytes = body::to_bytes(body).await;
         assert_eq!(bytes.unwrap(), "27");
     }
+
+    #[actix_web::test]
+    async fn test_invalid_number() {
+        let app = test::init_service(App::new().configure(super::configure)).await;
+        let req = test::TestRequest::get().uri("/1/4/x").to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
 }
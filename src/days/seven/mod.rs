@@ -1,8 +1,9 @@
 use std::collections::HashMap;
-use actix_web::{get, HttpRequest, HttpResponse, Responder, web};
+use actix_web::{get, HttpRequest, HttpResponse, web};
 use base64::Engine;
 use base64::engine::general_purpose;
-use serde::{Deserialize, Serialize};
+
+use crate::error::AppError;
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(part_1);
@@ -10,54 +11,19 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
 }
 
 #[get("/7/decode")]
-async fn part_1(req: HttpRequest) -> impl Responder {
-    match req.cookie("recipe") {
-        Some(cookie) => {
-            let value = cookie.value();
-            match base64::engine::general_purpose::STANDARD.decode(value) {
-                Ok(decoded) => {
-                    let decoded = String::from_utf8(decoded).unwrap();
-                    HttpResponse::Ok().body(decoded)
-                }
-                Err(_) => HttpResponse::BadRequest().body("Invalid base64"),
-            }
-        }
-        None => HttpResponse::BadRequest().body("No cookie found"),
-    }
+async fn part_1(req: HttpRequest) -> Result<HttpResponse, AppError> {
+    let decoded = decode_recipe(&req)?;
+    let decoded = String::from_utf8(decoded).map_err(|_| AppError::bad_request("Invalid UTF-8"))?;
+    Ok(HttpResponse::Ok().body(decoded))
 }
 
-#[derive(Serialize, Deserialize)]
-struct Recipe {
-    flour: u32,
-    sugar: u32,
-    butter: u32,
-    #[serde(rename = "baking powder")]
-    baking_powder: u32,
-    #[serde(rename = "chocolate chips")]
-    chocolate_chips: u32,
-}
-
-#[derive(Serialize, Deserialize)]
-struct Pantry {
-    flour: u32,
-    sugar: u32,
-    butter: u32,
-    #[serde(rename = "baking powder")]
-    baking_powder: u32,
-    #[serde(rename = "chocolate chips")]
-    chocolate_chips: u32,
-}
-
-#[derive(Serialize, Deserialize)]
-struct BakeCookie {
-    recipe: Recipe,
-    pantry: Pantry,
-}
-
-#[derive(Serialize, Deserialize)]
-struct BakeCookieResponse {
-    cookies: u32,
-    pantry: Pantry,
+fn decode_recipe(req: &HttpRequest) -> Result<Vec<u8>, AppError> {
+    let cookie = req
+        .cookie("recipe")
+        .ok_or_else(|| AppError::bad_request("No cookie found"))?;
+    general_purpose::STANDARD
+        .decode(cookie.value())
+        .map_err(|_| AppError::bad_request("Invalid base64"))
 }
 
 #[derive(serde::Deserialize)]
@@ -67,12 +33,9 @@ struct Bake {
 }
 
 #[get("/7/bake")]
-async fn part_2(request: HttpRequest) -> impl Responder {
-    let cookie = request.cookie("recipe").unwrap();
-    let encoded = cookie.value();
-
-    let decoded = general_purpose::STANDARD.decode(encoded).unwrap();
-    let mut bake: Bake = serde_json::from_slice(&decoded).unwrap();
+async fn part_2(request: HttpRequest) -> Result<HttpResponse, AppError> {
+    let decoded = decode_recipe(&request)?;
+    let mut bake: Bake = serde_json::from_slice(&decoded).map_err(AppError::bad_request)?;
 
     let cookies = bake
         .recipe
@@ -93,18 +56,36 @@ async fn part_2(request: HttpRequest) -> impl Responder {
         }
     }
 
-    HttpResponse::Ok().json(serde_json::json!(
+    Ok(HttpResponse::Ok().json(serde_json::json!(
         {
             "cookies": cookies,
             "pantry": bake.pantry,
         }
-    ))
+    )))
 }
 
 #[cfg(test)]
 mod tests {
     use actix_web::cookie::Cookie;
     use actix_web::{body, test, App};
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    struct Pantry {
+        flour: u32,
+        sugar: u32,
+        butter: u32,
+        #[serde(rename = "baking powder")]
+        baking_powder: u32,
+        #[serde(rename = "chocolate chips")]
+        chocolate_chips: u32,
+    }
+
+    #[derive(Deserialize)]
+    struct BakeCookieResponse {
+        cookies: u32,
+        pantry: Pantry,
+    }
 
     #[actix_web::test]
     async fn test_part_1() {
@@ -143,7 +124,7 @@ mod tests {
 
         let body = resp.into_body();
         let bytes = body::to_bytes(body).await.unwrap();
-        let response: super::BakeCookieResponse = serde_json::from_slice(&bytes).unwrap();
+        let response: BakeCookieResponse = serde_json::from_slice(&bytes).unwrap();
 
         assert_eq!(response.cookies, 4);
         assert_eq!(response.pantry.flour, 5);
@@ -152,4 +133,20 @@ mod tests {
         assert_eq!(response.pantry.baking_powder, 825);
         assert_eq!(response.pantry.chocolate_chips, 257);
     }
+
+    #[actix_web::test]
+    async fn test_missing_cookie() {
+        let app = test::init_service(App::new().configure(super::configure)).await;
+
+        let req = test::TestRequest::get().uri("/7/bake").to_request();
+
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+        let body = resp.into_body();
+        let bytes = body::to_bytes(body).await.unwrap();
+        let response: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(response["error"], "bad_request");
+    }
 }
@@ -1,103 +1,359 @@
-use actix_web::{get, HttpResponse, post, Responder, web};
+use actix_web::{get, HttpRequest, HttpResponse, post, web};
+use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
 
+use crate::error::AppError;
+use crate::migrations::RequireMigrations;
+use crate::orders::{AnalyticsQuery, InsertParams, MissingRegions, Order, OrderRepository};
+use crate::transfer::{self, ExportParams};
+
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(part_1);
     cfg.service(part_2_reset);
     cfg.service(part_2_orders);
     cfg.service(part_2_orders_total);
     cfg.service(part_3);
+    cfg.service(analytics);
+    cfg.service(export_orders);
 }
 
-#[get("/13/sql")]
+#[get("/13/sql", wrap = "RequireMigrations")]
 async fn part_1(
     pool: web::Data<PgPool>
-) -> impl Responder {
-    sqlx::query!("SELECT 20231213 number")
+) -> Result<String, AppError> {
+    let number = sqlx::query!("SELECT 20231213 number")
         .fetch_one(pool.as_ref())
-        .await
-        .unwrap()
+        .await?
         .number
-        .unwrap()
-        .to_string()
+        .unwrap_or_default();
+
+    Ok(number.to_string())
 }
 
-#[post("/13/reset")]
+#[post("/13/reset", wrap = "RequireMigrations")]
 async fn part_2_reset(
-    pool: web::Data<PgPool>
-) -> impl Responder {
-    sqlx::query!("DROP TABLE IF EXISTS orders")
-        .execute(pool.as_ref())
-        .await
-        .unwrap();
-
-    sqlx::query!("CREATE TABLE orders (
-        id INT PRIMARY KEY,
-        region_id INT,
-        gift_name VARCHAR(50),
-        quantity INT
-    )")
-        .execute(pool.as_ref())
-        .await
-        .unwrap();
-
-    HttpResponse::Ok()
-}
+    repository: web::Data<dyn OrderRepository>
+) -> Result<HttpResponse, AppError> {
+    repository.reset().await?;
 
-#[derive(serde::Deserialize)]
-struct Order {
-    id: i32,
-    region_id: i32,
-    gift_name: String,
-    quantity: i32,
+    Ok(HttpResponse::Ok().finish())
 }
 
-#[post("/13/orders")]
+#[post("/13/orders", wrap = "RequireMigrations")]
 async fn part_2_orders(
-    orders: web::Json<Vec<Order>>,
-    pool: web::Data<PgPool>,
-) -> impl Responder {
-    for order in orders.iter() {
-        sqlx::query!(
-            "INSERT INTO orders (id, region_id, gift_name, quantity) VALUES ($1, $2, $3, $4)",
-            order.id,
-            order.region_id,
-            order.gift_name,
-            order.quantity
-        )
-            .execute(pool.as_ref())
-            .await
-            .unwrap();
-    }
+    req: HttpRequest,
+    payload: web::Payload,
+    params: web::Query<InsertParams>,
+    repository: web::Data<dyn OrderRepository>,
+) -> Result<HttpResponse, AppError> {
+    let orders: Vec<Order> = transfer::read_rows(&req, payload).await?;
+
+    // day 13 has no notion of regions, so nameless ones are created to satisfy the foreign key
+    let report = repository.insert_orders(&orders, params.on_conflict, MissingRegions::Create).await?;
+
+    Ok(report.into_response())
+}
 
-    HttpResponse::Ok()
+#[get("/13/orders/export", wrap = "RequireMigrations")]
+async fn export_orders(
+    params: web::Query<ExportParams>,
+    repository: web::Data<dyn OrderRepository>,
+) -> Result<HttpResponse, AppError> {
+    Ok(transfer::write_rows(params.format, repository.all_orders().await?))
 }
 
-#[get("/13/orders/total")]
+#[get("/13/orders/total", wrap = "RequireMigrations")]
 async fn part_2_orders_total(
-    pool: web::Data<PgPool>
-) -> impl Responder {
-    let total = sqlx::query!("SELECT SUM(quantity) total FROM orders")
-        .fetch_one(pool.as_ref())
-        .await
-        .unwrap()
-        .total
-        .unwrap();
+    repository: web::Data<dyn OrderRepository>
+) -> Result<HttpResponse, AppError> {
+    let total = repository.total_quantity().await?;
 
-    HttpResponse::Ok().json(serde_json::json!({ "total": total }))
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "total": total })))
 }
 
-#[get("/13/orders/popular")]
+#[get("/13/orders/popular", wrap = "RequireMigrations")]
 async fn part_3(
-    pool: web::Data<PgPool>
-) -> impl Responder {
-    let popular = sqlx::query!("SELECT gift_name, SUM(quantity) total FROM orders GROUP BY gift_name ORDER BY total DESC LIMIT 1")
-        .fetch_one(pool.as_ref())
-        .await;
+    repository: web::Data<dyn OrderRepository>
+) -> Result<HttpResponse, AppError> {
+    let popular = repository.most_popular_gift().await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "popular": popular })))
+}
+
+const MAX_TOP: i64 = 1000;
+
+#[derive(Deserialize)]
+struct AnalyticsParams {
+    /// `gift`, `region` or both, comma-separated.
+    group_by: Option<String>,
+    /// Comma-separated region ids.
+    region_ids: Option<String>,
+    gift_prefix: Option<String>,
+    min_quantity: Option<i32>,
+    top: Option<i64>,
+}
+
+impl AnalyticsParams {
+    fn into_query(self) -> Result<AnalyticsQuery, AppError> {
+        let group_by = self.group_by.unwrap_or_else(|| "gift".to_string());
+        let mut by_gift = false;
+        let mut by_region = false;
+        for key in group_by.split(',') {
+            match key.trim() {
+                "gift" => by_gift = true,
+                "region" => by_region = true,
+                _ => return Err(AppError::BadRequest(format!("cannot group by {}", key))),
+            }
+        }
+
+        let region_ids = self.region_ids
+            .map(|ids| {
+                ids.split(',')
+                    .map(|id| id.trim().parse().map_err(|_| AppError::BadRequest(format!("invalid region id: {}", id))))
+                    .collect::<Result<Vec<i32>, AppError>>()
+            })
+            .transpose()?;
+
+        let top = self.top.unwrap_or(10);
+        if !(1..=MAX_TOP).contains(&top) {
+            return Err(AppError::BadRequest(format!("top must be between 1 and {}", MAX_TOP)));
+        }
+
+        Ok(AnalyticsQuery {
+            by_gift,
+            by_region,
+            region_ids,
+            gift_prefix: self.gift_prefix,
+            min_quantity: self.min_quantity,
+            top,
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct AnalyticsGroup {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gift_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    region_id: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    region: Option<String>,
+    quantity: i64,
+    /// Percentage of the total quantity, rounded to two decimals.
+    share: f64,
+}
+
+#[derive(Serialize)]
+struct AnalyticsResponse {
+    total: i64,
+    groups: Vec<AnalyticsGroup>,
+}
+
+#[get("/13/orders/analytics", wrap = "RequireMigrations")]
+async fn analytics(
+    params: web::Query<AnalyticsParams>,
+    repository: web::Data<dyn OrderRepository>,
+) -> Result<HttpResponse, AppError> {
+    let analytics = repository.analytics(&params.into_inner().into_query()?).await?;
+
+    let total = analytics.total;
+    let groups = analytics.groups
+        .into_iter()
+        .map(|group| AnalyticsGroup {
+            share: if total == 0 { 0.0 } else { (group.quantity as f64 * 10000.0 / total as f64).round() / 100.0 },
+            gift_name: group.gift_name,
+            region_id: group.region_id,
+            region: group.region,
+            quantity: group.quantity,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(AnalyticsResponse { total, groups }))
+}
 
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
 
-    return HttpResponse::Ok().json(match popular {
-        Err(_) => serde_json::json!({ "popular" : null }),
-        Ok(popular) =>serde_json::json!( { "popular": popular.gift_name })
-    })
+    use actix_web::{App, body, test, web};
+    use serde_json::json;
+
+    use crate::orders::{MemoryOrderRepository, OrderRepository};
+
+    fn configure(cfg: &mut web::ServiceConfig) {
+        let repository: Arc<dyn OrderRepository> = Arc::new(MemoryOrderRepository::default());
+        cfg.app_data(web::Data::from(repository));
+        super::configure(cfg);
+    }
+
+    #[actix_web::test]
+    async fn test_part_2_and_3() {
+        let app = test::init_service(App::new().configure(configure)).await;
+
+        let req = test::TestRequest::get().uri("/13/orders/popular").to_request();
+        let resp = test::call_service(&app, req).await;
+        let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&bytes).unwrap(), json!({ "popular": null }));
+
+        let req = test::TestRequest::post()
+            .uri("/13/orders")
+            .set_json(json!([
+                { "id": 1, "region_id": 2, "gift_name": "Toy Train", "quantity": 5 },
+                { "id": 2, "region_id": 2, "gift_name": "Doll", "quantity": 8 },
+                { "id": 3, "region_id": 3, "gift_name": "Toy Train", "quantity": 4 }
+            ]))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let req = test::TestRequest::get().uri("/13/orders/total").to_request();
+        let resp = test::call_service(&app, req).await;
+        let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&bytes).unwrap(), json!({ "total": 17 }));
+
+        let req = test::TestRequest::get().uri("/13/orders/popular").to_request();
+        let resp = test::call_service(&app, req).await;
+        let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&bytes).unwrap(), json!({ "popular": "Toy Train" }));
+
+        let req = test::TestRequest::post().uri("/13/reset").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let req = test::TestRequest::get().uri("/13/orders/total").to_request();
+        let resp = test::call_service(&app, req).await;
+        let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&bytes).unwrap(), json!({ "total": 0 }));
+    }
+
+    #[actix_web::test]
+    async fn test_on_conflict() {
+        let app = test::init_service(App::new().configure(configure)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/13/orders")
+            .set_json(json!([{ "id": 1, "region_id": 1, "gift_name": "Doll", "quantity": 1 }]))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let batch = json!([
+            { "id": 1, "region_id": 1, "gift_name": "Doll", "quantity": 10 },
+            { "id": 2, "region_id": 1, "gift_name": "Doll", "quantity": 1 },
+            { "id": 2, "region_id": 1, "gift_name": "Doll", "quantity": 1 }
+        ]);
+
+        let req = test::TestRequest::post().uri("/13/orders").set_json(&batch).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::CONFLICT);
+        let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+        let report: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(report["committed"], false);
+        assert_eq!(report["rejected"], 2);
+        assert_eq!(report["rows"][0]["reason"], "order id 1 already exists");
+        assert_eq!(report["rows"][1]["reason"], "duplicate order id 2 in batch");
+
+        let req = test::TestRequest::post().uri("/13/orders?on_conflict=skip").set_json(&batch).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+        let report: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!((report["inserted"].clone(), report["skipped"].clone(), report["rejected"].clone()), (json!(1), json!(1), json!(1)));
+
+        let req = test::TestRequest::post().uri("/13/orders?on_conflict=upsert").set_json(&batch).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+        let report: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!((report["inserted"].clone(), report["updated"].clone()), (json!(0), json!(2)));
+
+        let req = test::TestRequest::get().uri("/13/orders/total").to_request();
+        let resp = test::call_service(&app, req).await;
+        let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&bytes).unwrap(), json!({ "total": 11 }));
+
+        let req = test::TestRequest::post()
+            .uri("/13/orders")
+            .set_json(json!([{ "id": 3, "region_id": 1, "quantity": 1 }]))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::CONFLICT);
+        let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+        let report: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(report["rows"][0]["reason"], "order 3 has no gift name");
+    }
+
+    #[actix_web::test]
+    async fn test_analytics() {
+        let app = test::init_service(App::new().configure(configure)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/13/orders")
+            .set_json(json!([
+                { "id": 1, "region_id": 1, "gift_name": "Toy Train", "quantity": 5 },
+                { "id": 2, "region_id": 2, "gift_name": "Toy Train", "quantity": 3 },
+                { "id": 3, "region_id": 1, "gift_name": "Doll", "quantity": 8 },
+                { "id": 4, "region_id": 2, "gift_name": "Toy Robot", "quantity": 4 },
+                { "id": 5, "region_id": 3, "gift_name": "Ball", "quantity": 1 }
+            ]))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let req = test::TestRequest::get().uri("/13/orders/analytics?top=2").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+        // Doll and Toy Train tie, so they are ordered by name
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&bytes).unwrap(), json!({
+            "total": 21,
+            "groups": [
+                { "gift_name": "Doll", "quantity": 8, "share": 38.1 },
+                { "gift_name": "Toy Train", "quantity": 8, "share": 38.1 }
+            ]
+        }));
+
+        let req = test::TestRequest::get()
+            .uri("/13/orders/analytics?group_by=gift,region&region_ids=1,2&gift_prefix=Toy&min_quantity=4")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&bytes).unwrap(), json!({
+            "total": 9,
+            "groups": [
+                { "gift_name": "Toy Train", "region_id": 1, "quantity": 5, "share": 55.56 },
+                { "gift_name": "Toy Robot", "region_id": 2, "quantity": 4, "share": 44.44 }
+            ]
+        }));
+
+        let req = test::TestRequest::get().uri("/13/orders/analytics?group_by=season").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_csv_import_and_ndjson_export() {
+        let app = test::init_service(App::new().configure(configure)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/13/orders")
+            .insert_header(("content-type", "text/csv"))
+            .set_payload("id,region_id,gift_name,quantity\n2,1,Doll,8\n1,2,\"Toy Train, Deluxe\",5\n")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let req = test::TestRequest::get().uri("/13/orders/export?format=ndjson").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        assert_eq!(resp.headers().get("content-type").unwrap(), "application/x-ndjson");
+
+        let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(
+            String::from_utf8(bytes.to_vec()).unwrap(),
+            "{\"id\":1,\"region_id\":2,\"gift_name\":\"Toy Train, Deluxe\",\"quantity\":5}\n\
+            {\"id\":2,\"region_id\":1,\"gift_name\":\"Doll\",\"quantity\":8}\n"
+        );
+    }
 }
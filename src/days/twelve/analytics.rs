@@ -0,0 +1,232 @@
+use std::collections::{BTreeMap, HashMap};
+
+use actix_web::{post, web, HttpResponse};
+use chrono::{DateTime, Datelike, SecondsFormat, TimeZone, Timelike, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use crate::error::AppError;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(analytics);
+}
+
+#[derive(Deserialize)]
+struct AnalyticsRequest {
+    ulids: Vec<Ulid>,
+    /// An IANA time zone name such as `Europe/Brussels`; dates are evaluated in UTC by default.
+    timezone: Option<String>,
+    /// Named predicates such as `"christmas_eve": "month=12&day=24"`.
+    #[serde(default)]
+    predicates: HashMap<String, String>,
+}
+
+#[derive(Serialize, Default)]
+struct Histograms {
+    year: BTreeMap<i32, usize>,
+    /// Indexed by month, January first.
+    month: [usize; 12],
+    /// Indexed by weekday, Monday first.
+    weekday: [usize; 7],
+    hour: [usize; 24],
+}
+
+#[derive(Serialize)]
+struct AnalyticsResponse {
+    timezone: String,
+    count: usize,
+    min: Option<String>,
+    max: Option<String>,
+    median: Option<String>,
+    histograms: Histograms,
+    predicates: HashMap<String, usize>,
+}
+
+enum Field {
+    Year(i32),
+    Month(u32),
+    Day(u32),
+    Weekday(Weekday),
+    Hour(u32),
+    Minute(u32),
+}
+
+impl Field {
+    fn matches(&self, date: &DateTime<Tz>) -> bool {
+        match *self {
+            Field::Year(year) => date.year() == year,
+            Field::Month(month) => date.month() == month,
+            Field::Day(day) => date.day() == day,
+            Field::Weekday(weekday) => date.weekday() == weekday,
+            Field::Hour(hour) => date.hour() == hour,
+            Field::Minute(minute) => date.minute() == minute,
+        }
+    }
+}
+
+/// A conjunction of `field=value` conditions joined by `&`. Weekdays are numbered from Monday,
+/// as in `/12/ulids/{weekday}`, or named (`fri`, `Friday`).
+struct Predicate(Vec<Field>);
+
+impl Predicate {
+    fn parse(input: &str) -> Result<Self, AppError> {
+        let invalid = || AppError::BadRequest(format!("invalid predicate: {}", input));
+
+        input
+            .split('&')
+            .map(|condition| {
+                let (field, value) = condition.split_once('=').ok_or_else(invalid)?;
+                let value = value.trim();
+                let number = || value.parse::<u32>().map_err(|_| invalid());
+                let field = match field.trim() {
+                    "year" => Field::Year(value.parse().map_err(|_| invalid())?),
+                    "month" => Field::Month(number()?),
+                    "day" => Field::Day(number()?),
+                    "weekday" => Field::Weekday(match value.parse::<u8>() {
+                        Ok(weekday) => Weekday::try_from(weekday).map_err(|_| invalid())?,
+                        Err(_) => value.parse().map_err(|_| invalid())?,
+                    }),
+                    "hour" => Field::Hour(number()?),
+                    "minute" => Field::Minute(number()?),
+                    _ => return Err(invalid()),
+                };
+                Ok(field)
+            })
+            .collect::<Result<Vec<Field>, AppError>>()
+            .map(Predicate)
+    }
+
+    fn matches(&self, date: &DateTime<Tz>) -> bool {
+        self.0.iter().all(|field| field.matches(date))
+    }
+}
+
+fn format_millis(timezone: &Tz, millis: i64) -> Option<String> {
+    timezone
+        .timestamp_millis_opt(millis)
+        .single()
+        .map(|date| date.to_rfc3339_opts(SecondsFormat::Millis, true))
+}
+
+#[post("/12/ulid/analytics")]
+async fn analytics(request: web::Json<AnalyticsRequest>) -> Result<HttpResponse, AppError> {
+    let request = request.into_inner();
+
+    let timezone: Tz = match request.timezone.as_deref() {
+        Some(name) => name.parse().map_err(|_| AppError::BadRequest(format!("unknown time zone: {}", name)))?,
+        None => Tz::UTC,
+    };
+    let predicates = request.predicates
+        .into_iter()
+        .map(|(name, predicate)| Ok((name, Predicate::parse(&predicate)?)))
+        .collect::<Result<Vec<_>, AppError>>()?;
+
+    let mut timestamps: Vec<i64> = request.ulids.iter().map(|ulid| ulid.timestamp_ms() as i64).collect();
+    timestamps.sort_unstable();
+
+    let mut histograms = Histograms::default();
+    let mut counts: HashMap<String, usize> = predicates.iter().map(|(name, _)| (name.clone(), 0)).collect();
+    for &millis in &timestamps {
+        let Some(date) = timezone.timestamp_millis_opt(millis).single() else {
+            continue;
+        };
+
+        *histograms.year.entry(date.year()).or_default() += 1;
+        histograms.month[date.month0() as usize] += 1;
+        histograms.weekday[date.weekday().num_days_from_monday() as usize] += 1;
+        histograms.hour[date.hour() as usize] += 1;
+
+        for (name, predicate) in &predicates {
+            if predicate.matches(&date) {
+                *counts.entry(name.clone()).or_default() += 1;
+            }
+        }
+    }
+
+    let median = match timestamps.len() {
+        0 => None,
+        len if len % 2 == 1 => Some(timestamps[len / 2]),
+        len => Some(timestamps[len / 2 - 1] + (timestamps[len / 2] - timestamps[len / 2 - 1]) / 2),
+    };
+
+    Ok(HttpResponse::Ok().json(AnalyticsResponse {
+        timezone: timezone.name().to_string(),
+        count: timestamps.len(),
+        min: timestamps.first().and_then(|&millis| format_millis(&timezone, millis)),
+        max: timestamps.last().and_then(|&millis| format_millis(&timezone, millis)),
+        median: median.and_then(|millis| format_millis(&timezone, millis)),
+        histograms,
+        predicates: counts,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{body, test, App};
+    use serde_json::json;
+
+    const ULIDS: [&str; 9] = [
+        "00WEGGF0G0J5HEYXS3D7RWZGV8",
+        "76EP4G39R8JD1N8AQNYDVJBRCF",
+        "018CJ7KMG0051CDCS3B7BFJ3AK",
+        "00Y986KPG0AMGB78RD45E9109K",
+        "010451HTG0NYWMPWCEXG6AJ8F2",
+        "01HH9SJEG0KY16H81S3N1BMXM4",
+        "01HH9SJEG0P9M22Z9VGHH9C8CX",
+        "017F8YY0G0NQA16HHC2QT5JD6X",
+        "03QCPC7P003V1NND3B3QJW72QJ",
+    ];
+
+    #[actix_web::test]
+    async fn test_analytics() {
+        let app = test::init_service(App::new().configure(super::configure)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/12/ulid/analytics")
+            .set_json(json!({
+                "ulids": ULIDS,
+                "timezone": "America/New_York",
+                "predicates": {
+                    "christmas_eve": "month=12&day=24",
+                    "thursday": "weekday=thu",
+                    "saturday": "weekday=5"
+                }
+            }))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+
+        let body = resp.into_body();
+        let bytes = body::to_bytes(body).await.unwrap();
+        let response: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+
+        assert_eq!(response["count"], 9);
+        assert_eq!(response["min"], "2000-12-24T07:00:00.000-05:00");
+        // chrono-tz does not project daylight saving time that far ahead, so only check the date
+        assert!(response["max"].as_str().unwrap().starts_with("9999-09-09T"));
+        assert_eq!(response["median"], "2013-12-24T07:00:00.000-05:00");
+        assert_eq!(response["predicates"], json!({ "christmas_eve": 3, "thursday": 2, "saturday": 1 }));
+        // 2100-01-01T00:00Z is still New Year's Eve in New York
+        assert_eq!(response["histograms"]["year"]["2099"], 1);
+        assert_eq!(response["histograms"]["month"], json!([0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 8]));
+        assert_eq!(response["histograms"]["hour"][7], 7);
+    }
+
+    #[actix_web::test]
+    async fn test_invalid_analytics() {
+        let app = test::init_service(App::new().configure(super::configure)).await;
+
+        for request in [
+            json!({ "ulids": ULIDS, "timezone": "Mars/Olympus_Mons" }),
+            json!({ "ulids": ULIDS, "predicates": { "bad": "month" } }),
+            json!({ "ulids": ULIDS, "predicates": { "bad": "season=winter" } }),
+        ] {
+            let req = test::TestRequest::post().uri("/12/ulid/analytics").set_json(request).to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+        }
+    }
+}
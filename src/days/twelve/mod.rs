@@ -1,44 +1,75 @@
-use std::collections::HashMap;
-use std::time::Instant;
-
-use actix_web::{get, HttpResponse, post, Responder, web};
+use actix_web::{delete, get, HttpResponse, post, Responder, web};
 use chrono::{Datelike, DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use tokio::sync::Mutex;
 use ulid::serde::ulid_as_uuid;
 use ulid::Ulid;
 
-struct AppState {
-    strings: Mutex<HashMap<String, Instant>>,
-}
+use crate::error::AppError;
+use crate::migrations::RequireMigrations;
+
+use self::store::PacketStore;
+
+pub mod analytics;
+pub mod store;
+pub mod ulids;
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
-    cfg.app_data(web::Data::new(AppState {
-        strings: Mutex::new(HashMap::new()),
-    }));
     cfg.service(part_1_save);
     cfg.service(part_1_load);
+    cfg.service(packets);
+    cfg.service(delete_packet);
     cfg.service(part_2);
     cfg.service(part_3);
+    cfg.configure(ulids::configure);
+    cfg.configure(analytics::configure);
+}
+
+#[derive(Deserialize)]
+struct SaveParams {
+    /// Seconds after which the packet is forgotten.
+    ttl: Option<u64>,
 }
 
-#[post("/12/save/{string}")]
-async fn part_1_save(string: web::Path<String>, state: web::Data<AppState>) -> impl Responder {
-    let mut strings = state.strings.lock().await;
-    strings.insert(string.into_inner(), Instant::now());
-    HttpResponse::Ok()
+#[post("/12/save/{string}", wrap = "RequireMigrations")]
+async fn part_1_save(
+    string: web::Path<String>,
+    params: web::Query<SaveParams>,
+    store: web::Data<dyn PacketStore>,
+) -> Result<HttpResponse, AppError> {
+    let ttl = params.ttl
+        .map(|ttl| {
+            chrono::Duration::from_std(std::time::Duration::from_secs(ttl))
+                .ok()
+                .filter(|ttl| Utc::now().checked_add_signed(*ttl).is_some())
+                .ok_or_else(|| AppError::BadRequest(format!("invalid ttl: {}", ttl)))
+        })
+        .transpose()?;
+
+    store.save(&string, ttl).await?;
+    Ok(HttpResponse::Ok().finish())
 }
 
-#[get("/12/load/{string}")]
-async fn part_1_load(string: web::Path<String>, state: web::Data<AppState>) -> impl Responder {
-    let strings = state.strings.lock().await;
-    let time = strings.get(&string.into_inner());
-    match time {
-        Some(time) => time.elapsed().as_secs().to_string(),
-        None => "0".to_string(),
+#[get("/12/load/{string}", wrap = "RequireMigrations")]
+async fn part_1_load(string: web::Path<String>, store: web::Data<dyn PacketStore>) -> Result<String, AppError> {
+    match store.load(&string).await? {
+        Some(packet) => Ok(packet.elapsed.to_string()),
+        None => Err(AppError::NotFound(format!("packet {} was never saved", string))),
     }
 }
 
+#[get("/12/packets", wrap = "RequireMigrations")]
+async fn packets(store: web::Data<dyn PacketStore>) -> Result<HttpResponse, AppError> {
+    Ok(HttpResponse::Ok().json(store.list().await?))
+}
+
+#[delete("/12/packets/{string}", wrap = "RequireMigrations")]
+async fn delete_packet(string: web::Path<String>, store: web::Data<dyn PacketStore>) -> Result<HttpResponse, AppError> {
+    if store.delete(&string).await? {
+        Ok(HttpResponse::Ok().finish())
+    } else {
+        Err(AppError::NotFound(format!("packet {} was never saved", string)))
+    }
+}
 
 #[derive(Serialize, Deserialize)]
 struct UlidUuid(#[serde(serialize_with = "ulid_as_uuid::serialize")] Ulid);
@@ -99,11 +130,21 @@ async fn part_3(weekday: web::Path<u32>, ulids: web::Json<Vec<Ulid>>) -> impl Re
 
 #[cfg(test)]
 mod tests {
-    use actix_web::{App, body, test};
+    use std::sync::Arc;
+
+    use actix_web::{App, body, test, web};
+
+    use super::store::{MemoryPacketStore, PacketStore};
+
+    fn configure(cfg: &mut web::ServiceConfig) {
+        let store: Arc<dyn PacketStore> = Arc::new(MemoryPacketStore::default());
+        cfg.app_data(web::Data::from(store));
+        super::configure(cfg);
+    }
 
     #[actix_web::test]
     async fn test_part_1() {
-        let app = test::init_service(App::new().configure(super::configure)).await;
+        let app = test::init_service(App::new().configure(configure)).await;
 
         let req = test::TestRequest::post()
             .uri("/12/save/packet20231212")
@@ -133,7 +174,7 @@ mod tests {
 
     #[actix_web::test]
     async fn test_part_2() {
-        let app = test::init_service(App::new().configure(super::configure)).await;
+        let app = test::init_service(App::new().configure(configure)).await;
 
         let ulids = vec![
             "01BJQ0E1C3Z56ABCD0E11HYX4M".to_string(),
@@ -169,7 +210,7 @@ mod tests {
 
     #[actix_web::test]
     async fn test_part_3() {
-        let app = test::init_service(App::new().configure(super::configure)).await;
+        let app = test::init_service(App::new().configure(configure)).await;
 
         let ulids = vec![
             "00WEGGF0G0J5HEYXS3D7RWZGV8".to_string(),
@@ -205,4 +246,59 @@ mod tests {
 
         assert_eq!(response, valid_resp);
     }
+
+    #[actix_web::test]
+    async fn test_part_1_never_saved() {
+        let app = test::init_service(App::new().configure(configure)).await;
+
+        let req = test::TestRequest::get()
+            .uri("/12/load/unknown")
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_packets() {
+        let app = test::init_service(App::new().configure(configure)).await;
+
+        for uri in ["/12/save/b", "/12/save/a?ttl=3600", "/12/save/expired?ttl=0"] {
+            let req = test::TestRequest::post().uri(uri).to_request();
+            let resp = test::call_service(&app, req).await;
+            assert!(resp.status().is_success());
+        }
+
+        let req = test::TestRequest::get().uri("/12/packets").to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+
+        let body = resp.into_body();
+        let bytes = body::to_bytes(body).await.unwrap();
+        let response: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+
+        let names: Vec<&str> = response.as_array().unwrap().iter().map(|p| p["name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["a", "b"]);
+        assert!(response[0]["expires_at"].is_string());
+        assert!(response[1]["expires_at"].is_null());
+
+        let req = test::TestRequest::delete().uri("/12/packets/a").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let req = test::TestRequest::get().uri("/12/load/a").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+
+        let req = test::TestRequest::delete().uri("/12/packets/a").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+
+        // as good as never saved
+        let req = test::TestRequest::delete().uri("/12/packets/expired").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
 }
@@ -0,0 +1,218 @@
+#[cfg(test)]
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+#[cfg(test)]
+use tokio::sync::Mutex;
+
+use crate::error::AppError;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Packet {
+    pub name: String,
+    pub saved_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Whole seconds since the packet was saved, by the same clock as `saved_at`.
+    pub elapsed: i64,
+}
+
+#[cfg(test)]
+impl Packet {
+    fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    fn at(&self, now: DateTime<Utc>) -> Packet {
+        Packet { elapsed: (now - self.saved_at).num_seconds().max(0), ..self.clone() }
+    }
+}
+
+/// Keeps track of when packets were last saved. Expired packets are treated as never saved.
+#[async_trait]
+pub trait PacketStore: Send + Sync {
+    async fn save(&self, name: &str, ttl: Option<Duration>) -> Result<Packet, AppError>;
+    async fn load(&self, name: &str) -> Result<Option<Packet>, AppError>;
+    async fn list(&self) -> Result<Vec<Packet>, AppError>;
+    /// Whether there was an unexpired packet to delete.
+    async fn delete(&self, name: &str) -> Result<bool, AppError>;
+}
+
+pub struct PgPacketStore {
+    pool: PgPool,
+}
+
+impl PgPacketStore {
+    pub fn new(pool: PgPool) -> Self {
+        PgPacketStore { pool }
+    }
+}
+
+#[async_trait]
+impl PacketStore for PgPacketStore {
+    async fn save(&self, name: &str, ttl: Option<Duration>) -> Result<Packet, AppError> {
+        // Expiry is checked against the database clock, so the timestamps are taken from it too.
+        let ttl_seconds = ttl.map(|ttl| ttl.num_milliseconds() as f64 / 1000.0);
+
+        sqlx::query!("DELETE FROM packets WHERE expires_at <= now()")
+            .execute(&self.pool)
+            .await?;
+
+        let packet = sqlx::query_as!(
+            Packet,
+            "INSERT INTO packets (name, saved_at, expires_at) VALUES ($1, now(), now() + make_interval(secs => $2))
+            ON CONFLICT (name) DO UPDATE SET saved_at = EXCLUDED.saved_at, expires_at = EXCLUDED.expires_at
+            RETURNING name, saved_at, expires_at, 0::bigint \"elapsed!\"",
+            name,
+            ttl_seconds
+        )
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(packet)
+    }
+
+    async fn load(&self, name: &str) -> Result<Option<Packet>, AppError> {
+        let packet = sqlx::query_as!(
+            Packet,
+            "SELECT name, saved_at, expires_at, GREATEST(FLOOR(EXTRACT(EPOCH FROM now() - saved_at)), 0)::bigint \"elapsed!\"
+            FROM packets
+            WHERE name = $1 AND (expires_at IS NULL OR expires_at > now())",
+            name
+        )
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(packet)
+    }
+
+    async fn list(&self) -> Result<Vec<Packet>, AppError> {
+        let packets = sqlx::query_as!(
+            Packet,
+            "SELECT name, saved_at, expires_at, GREATEST(FLOOR(EXTRACT(EPOCH FROM now() - saved_at)), 0)::bigint \"elapsed!\"
+            FROM packets
+            WHERE expires_at IS NULL OR expires_at > now()
+            ORDER BY name"
+        )
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(packets)
+    }
+
+    async fn delete(&self, name: &str) -> Result<bool, AppError> {
+        // an expired packet is deleted all the same, but was already as good as gone
+        let deleted = sqlx::query!(
+            "DELETE FROM packets WHERE name = $1 RETURNING expires_at IS NULL OR expires_at > now() \"live!\"",
+            name
+        )
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(deleted.is_some_and(|row| row.live))
+    }
+}
+
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryPacketStore {
+    packets: Mutex<HashMap<String, Packet>>,
+}
+
+#[cfg(test)]
+#[async_trait]
+impl PacketStore for MemoryPacketStore {
+    async fn save(&self, name: &str, ttl: Option<Duration>) -> Result<Packet, AppError> {
+        let saved_at = Utc::now();
+        let packet = Packet {
+            name: name.to_string(),
+            saved_at,
+            expires_at: ttl.map(|ttl| saved_at + ttl),
+            elapsed: 0,
+        };
+
+        let mut packets = self.packets.lock().await;
+        packets.retain(|_, packet| !packet.is_expired(saved_at));
+        packets.insert(name.to_string(), packet.clone());
+        Ok(packet)
+    }
+
+    async fn load(&self, name: &str) -> Result<Option<Packet>, AppError> {
+        let now = Utc::now();
+        let packets = self.packets.lock().await;
+        Ok(packets.get(name).filter(|packet| !packet.is_expired(now)).map(|packet| packet.at(now)))
+    }
+
+    async fn list(&self) -> Result<Vec<Packet>, AppError> {
+        let now = Utc::now();
+        let mut packets: Vec<Packet> = self.packets
+            .lock()
+            .await
+            .values()
+            .filter(|packet| !packet.is_expired(now))
+            .map(|packet| packet.at(now))
+            .collect();
+        packets.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(packets)
+    }
+
+    async fn delete(&self, name: &str) -> Result<bool, AppError> {
+        let removed = self.packets.lock().await.remove(name);
+        Ok(removed.is_some_and(|packet| !packet.is_expired(Utc::now())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use sqlx::PgPool;
+
+    use super::{PacketStore, PgPacketStore};
+
+    #[sqlx::test]
+    async fn test_save_and_load(pool: PgPool) {
+        let store = PgPacketStore::new(pool.clone());
+
+        let saved = store.save("b", None).await.unwrap();
+        assert_eq!((saved.name.as_str(), saved.elapsed, saved.expires_at), ("b", 0, None));
+        let saved = store.save("a", Some(Duration::seconds(3600))).await.unwrap();
+        assert_eq!(saved.expires_at, Some(saved.saved_at + Duration::seconds(3600)));
+        store.save("expired", Some(Duration::zero())).await.unwrap();
+
+        // elapsed time is measured by the database clock the packets were saved by
+        sqlx::query!("UPDATE packets SET saved_at = now() - interval '90 seconds' WHERE name = 'b'")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert_eq!(store.load("b").await.unwrap().unwrap().elapsed, 90);
+        assert!(store.load("expired").await.unwrap().is_none());
+        assert!(store.load("unknown").await.unwrap().is_none());
+
+        let packets: Vec<(String, i64)> = store
+            .list()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|packet| (packet.name, packet.elapsed))
+            .collect();
+        assert_eq!(packets, [("a".to_string(), 0), ("b".to_string(), 90)]);
+
+        // saving again starts over
+        store.save("b", None).await.unwrap();
+        assert_eq!(store.load("b").await.unwrap().unwrap().elapsed, 0);
+    }
+
+    #[sqlx::test]
+    async fn test_delete(pool: PgPool) {
+        let store = PgPacketStore::new(pool);
+        store.save("a", None).await.unwrap();
+        store.save("expired", Some(Duration::zero())).await.unwrap();
+
+        assert!(store.delete("a").await.unwrap());
+        assert!(!store.delete("a").await.unwrap());
+        assert!(!store.delete("expired").await.unwrap());
+        assert!(store.list().await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,250 @@
+use std::time::{Duration, SystemTime};
+
+use actix_web::{get, post, web, HttpResponse};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use ulid::{Generator, Ulid};
+use uuid::Uuid;
+
+use crate::error::AppError;
+
+const MAX_MINT_COUNT: usize = 1000;
+// ULID timestamps are 48-bit milliseconds since the Unix epoch.
+const MAX_TIMESTAMP_MS: u64 = (1 << 48) - 1;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(mint);
+    cfg.service(to_uuid);
+    cfg.service(from_uuid);
+    cfg.service(decode);
+}
+
+#[derive(Deserialize)]
+struct MintParams {
+    count: Option<usize>,
+    /// Milliseconds since the Unix epoch or an RFC 3339 date-time.
+    timestamp: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Base32Parts {
+    timestamp: String,
+    random: String,
+}
+
+#[derive(Serialize)]
+struct DecodedUlid {
+    ulid: String,
+    timestamp: u64,
+    datetime: String,
+    /// The 80-bit random component as hexadecimal.
+    random: String,
+    uuid: String,
+    base32: Base32Parts,
+}
+
+impl From<Ulid> for DecodedUlid {
+    fn from(ulid: Ulid) -> Self {
+        let encoded = ulid.to_string();
+        let datetime: DateTime<Utc> = ulid.datetime().into();
+        DecodedUlid {
+            timestamp: ulid.timestamp_ms(),
+            datetime: datetime.to_rfc3339_opts(SecondsFormat::Millis, true),
+            random: format!("{:020x}", ulid.random()),
+            uuid: Uuid::from(ulid).to_string(),
+            base32: Base32Parts {
+                timestamp: encoded[..10].to_string(),
+                random: encoded[10..].to_string(),
+            },
+            ulid: encoded,
+        }
+    }
+}
+
+/// The outcome of converting a single entry of a batch; exactly one of `output` and `error` is set.
+#[derive(Serialize)]
+struct Conversion {
+    input: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+fn convert_all(inputs: Vec<String>, convert: impl Fn(&str) -> Result<String, String>) -> Vec<Conversion> {
+    inputs
+        .into_iter()
+        .map(|input| {
+            let (output, error) = match convert(&input) {
+                Ok(output) => (Some(output), None),
+                Err(error) => (None, Some(error)),
+            };
+            Conversion { input, output, error }
+        })
+        .collect()
+}
+
+fn parse_timestamp(input: &str) -> Result<SystemTime, AppError> {
+    let millis = match input.parse::<u64>() {
+        Ok(millis) => Some(millis),
+        Err(_) => DateTime::parse_from_rfc3339(input)
+            .ok()
+            .and_then(|datetime| u64::try_from(datetime.timestamp_millis()).ok()),
+    };
+
+    millis
+        .filter(|millis| *millis <= MAX_TIMESTAMP_MS)
+        .map(|millis| SystemTime::UNIX_EPOCH + Duration::from_millis(millis))
+        .ok_or_else(|| AppError::BadRequest(format!("invalid timestamp: {}", input)))
+}
+
+/// Mints `count` ULIDs that are strictly increasing within the batch, even when they share a
+/// millisecond.
+#[post("/12/ulid/mint")]
+async fn mint(params: web::Query<MintParams>) -> Result<HttpResponse, AppError> {
+    let count = params.count.unwrap_or(1);
+    if count == 0 || count > MAX_MINT_COUNT {
+        return Err(AppError::BadRequest(format!("count must be between 1 and {}", MAX_MINT_COUNT)));
+    }
+    let datetime = params.timestamp.as_deref().map(parse_timestamp).transpose()?;
+
+    let mut generator = Generator::new();
+    let ulids = (0..count)
+        .map(|_| {
+            let ulid = match datetime {
+                Some(datetime) => generator.generate_from_datetime(datetime),
+                None => generator.generate(),
+            };
+            ulid.map(|ulid| ulid.to_string()).map_err(AppError::internal)
+        })
+        .collect::<Result<Vec<String>, AppError>>()?;
+
+    Ok(HttpResponse::Ok().json(ulids))
+}
+
+#[get("/12/ulid/{ulid}")]
+async fn decode(ulid: web::Path<String>) -> Result<HttpResponse, AppError> {
+    let ulid = Ulid::from_string(&ulid).map_err(|err| AppError::BadRequest(format!("invalid ulid {}: {}", ulid, err)))?;
+
+    Ok(HttpResponse::Ok().json(DecodedUlid::from(ulid)))
+}
+
+#[post("/12/ulid/to-uuid")]
+async fn to_uuid(ulids: web::Json<Vec<String>>) -> HttpResponse {
+    HttpResponse::Ok().json(convert_all(ulids.into_inner(), |input| {
+        Ulid::from_string(input)
+            .map(|ulid| Uuid::from(ulid).to_string())
+            .map_err(|err| format!("invalid ulid: {}", err))
+    }))
+}
+
+#[post("/12/ulid/from-uuid")]
+async fn from_uuid(uuids: web::Json<Vec<String>>) -> HttpResponse {
+    HttpResponse::Ok().json(convert_all(uuids.into_inner(), |input| {
+        Uuid::parse_str(input)
+            .map(|uuid| Ulid::from(uuid).to_string())
+            .map_err(|err| format!("invalid uuid: {}", err))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{body, test, App};
+    use serde_json::json;
+    use ulid::Ulid;
+
+    #[actix_web::test]
+    async fn test_mint() {
+        let app = test::init_service(App::new().configure(super::configure)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/12/ulid/mint?count=50&timestamp=2023-12-24T12:00:00Z")
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+
+        let body = resp.into_body();
+        let bytes = body::to_bytes(body).await.unwrap();
+        let response: Vec<Ulid> = serde_json::from_slice(&bytes).unwrap();
+
+        assert_eq!(response.len(), 50);
+        assert!(response.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(response.iter().all(|ulid| ulid.timestamp_ms() == 1703419200000));
+
+        let req = test::TestRequest::post().uri("/12/ulid/mint?count=0").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_decode() {
+        let app = test::init_service(App::new().configure(super::configure)).await;
+
+        let req = test::TestRequest::get().uri("/12/ulid/01BJQ0E1C3Z56ABCD0E11HYX4M").to_request();
+
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+
+        let body = resp.into_body();
+        let bytes = body::to_bytes(body).await.unwrap();
+        let response: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+
+        assert_eq!(response, json!({
+            "ulid": "01BJQ0E1C3Z56ABCD0E11HYX4M",
+            "timestamp": 1497568314755u64,
+            "datetime": "2017-06-15T23:11:54.755Z",
+            "random": "f94ca5b1a070431f7494",
+            "uuid": "015cae07-0583-f94c-a5b1-a070431f7494",
+            "base32": { "timestamp": "01BJQ0E1C3", "random": "Z56ABCD0E11HYX4M" }
+        }));
+
+        let req = test::TestRequest::get().uri("/12/ulid/not-a-ulid").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_conversions() {
+        let app = test::init_service(App::new().configure(super::configure)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/12/ulid/to-uuid")
+            .set_json(json!(["01BJQ0E1C3Z56ABCD0E11HYX4M", "nope"]))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+
+        let body = resp.into_body();
+        let bytes = body::to_bytes(body).await.unwrap();
+        let response: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+
+        assert_eq!(response[0], json!({
+            "input": "01BJQ0E1C3Z56ABCD0E11HYX4M",
+            "output": "015cae07-0583-f94c-a5b1-a070431f7494"
+        }));
+        assert_eq!(response[1]["input"], "nope");
+        assert!(response[1]["error"].is_string());
+        assert!(response[1].get("output").is_none());
+
+        let req = test::TestRequest::post()
+            .uri("/12/ulid/from-uuid")
+            .set_json(json!(["015cae07-0583-f94c-a5b1-a070431f7494", "015cae07"]))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+
+        let body = resp.into_body();
+        let bytes = body::to_bytes(body).await.unwrap();
+        let response: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+
+        assert_eq!(response[0]["output"], "01BJQ0E1C3Z56ABCD0E11HYX4M");
+        assert!(response[1]["error"].is_string());
+    }
+}
@@ -1,57 +1,57 @@
 use std::fs::File;
 
-use actix_web::{HttpResponse, post, Responder, web};
+use actix_web::{HttpResponse, post, web};
 use async_tempfile::TempFile;
 use futures::StreamExt as _;
 use git2::Repository;
 use tar::Archive;
 use tokio::io::AsyncWriteExt;
 
+use crate::error::AppError;
+
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(part_1_1);
     cfg.service(part_1_2);
     cfg.service(part_2);
 }
 
-async fn receive_tar(mut body: web::Payload) -> actix_web::Result<TempFile> {
-    let mut result = TempFile::new().await.unwrap();
+async fn receive_tar(mut body: web::Payload) -> Result<Archive<File>, AppError> {
+    let mut result = TempFile::new().await.map_err(AppError::internal)?;
     while let Some(item) = body.next().await {
-        result.write_all(&item?).await?;
+        result.write_all(&item.map_err(AppError::bad_request)?).await.map_err(AppError::internal)?;
     }
-    result.sync_all().await?;
-    Ok(result)
+    result.sync_all().await.map_err(AppError::internal)?;
+    let file = File::open(result.file_path()).map_err(AppError::internal)?;
+    Ok(Archive::new(file))
 }
 
 #[post("/20/archive_files")]
-async fn part_1_1(body: web::Payload) -> impl Responder {
-    let temp_file = receive_tar(body).await.unwrap();
-    let file = File::open(temp_file.file_path()).unwrap();
-    let mut archive = Archive::new(file);
-    let result = archive.entries().unwrap().count();
-    HttpResponse::Ok().body(result.to_string())
+async fn part_1_1(body: web::Payload) -> Result<HttpResponse, AppError> {
+    let mut archive = receive_tar(body).await?;
+    let result = archive.entries().map_err(AppError::bad_request)?.count();
+    Ok(HttpResponse::Ok().body(result.to_string()))
 }
 
 #[post("/20/archive_files_size")]
-async fn part_1_2(body: web::Payload) -> impl Responder {
-    let temp_file = receive_tar(body).await.unwrap();
-    let file = File::open(temp_file.file_path()).unwrap();
-    let mut archive = Archive::new(file);
-    let result: u64 = archive.entries().unwrap().map(|x| x.unwrap().size()).sum();
-    HttpResponse::Ok().body(result.to_string())
+async fn part_1_2(body: web::Payload) -> Result<HttpResponse, AppError> {
+    let mut archive = receive_tar(body).await?;
+    let mut result: u64 = 0;
+    for entry in archive.entries().map_err(AppError::bad_request)? {
+        result += entry.map_err(AppError::bad_request)?.size();
+    }
+    Ok(HttpResponse::Ok().body(result.to_string()))
 }
 
 
 #[post("/20/cookie")]
-async fn part_2(body: web::Payload) -> impl Responder {
-    let temp_file = receive_tar(body).await.unwrap();
-    let file = File::open(temp_file.file_path()).unwrap();
-    let mut archive = Archive::new(file);
-    let temp_dir = tempfile::TempDir::new().unwrap();
-    archive.unpack(temp_dir.path()).unwrap();
+async fn part_2(body: web::Payload) -> Result<HttpResponse, AppError> {
+    let mut archive = receive_tar(body).await?;
+    let temp_dir = tempfile::TempDir::new().map_err(AppError::internal)?;
+    archive.unpack(temp_dir.path()).map_err(AppError::bad_request)?;
     if let Some((author, hash)) = find_commit_author_and_hash(&temp_dir) {
-        return HttpResponse::Ok().body(format!("{} {}", author, hash));
+        return Ok(HttpResponse::Ok().body(format!("{} {}", author, hash)));
     }
-    HttpResponse::Ok().body("".to_string())
+    Ok(HttpResponse::Ok().body("".to_string()))
 }
 
 fn find_commit_author_and_hash(temp_dir: &tempfile::TempDir) -> Option<(String, String)> {
@@ -82,7 +82,7 @@ fn find_commit_with_santa_file<'a>(repo: &Repository, commit: git2::Commit<'a>)
     // Get the tree associated with the commit
     if let Ok(tree) = commit.tree() {
         // Traverse the tree
-        if let Some(path) = find_santa_file_in_tree(repo, &tree, vec![]) {
+        if find_santa_file_in_tree(repo, &tree, vec![]).is_some() {
             // If the file is found in this commit's tree, return the commit
             return Some(commit);
         }
@@ -0,0 +1,255 @@
+use actix_web::{get, web, HttpResponse};
+use s2::cell::Cell;
+use s2::cellid::CellID;
+use s2::latlng::LatLng;
+use s2::point::Point;
+use serde::{Deserialize, Serialize};
+
+use crate::error::AppError;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(cell_info);
+    cfg.service(cell_parent);
+    cfg.service(cell_children);
+    cfg.service(cell_neighbors);
+    cfg.service(cell_vertices);
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    Binary,
+    Decimal,
+    Token,
+}
+
+#[derive(Deserialize)]
+struct CellParams {
+    encoding: Option<Encoding>,
+    level: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Coordinates {
+    pub lat: f64,
+    pub lng: f64,
+}
+
+impl From<Point> for Coordinates {
+    fn from(point: Point) -> Self {
+        let latlng = LatLng::from(point);
+        Coordinates {
+            lat: latlng.lat.deg(),
+            lng: latlng.lng.deg(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct CellSummary {
+    /// The 64-bit id as a decimal string, as it does not fit in a JavaScript number.
+    pub id: String,
+    pub token: String,
+    pub binary: String,
+    pub level: u64,
+    pub face: u8,
+}
+
+impl From<CellID> for CellSummary {
+    fn from(cell_id: CellID) -> Self {
+        CellSummary {
+            id: cell_id.0.to_string(),
+            token: cell_id.to_token(),
+            binary: format!("{:064b}", cell_id.0),
+            level: cell_id.level(),
+            face: cell_id.face(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct CellInfo {
+    #[serde(flatten)]
+    cell: CellSummary,
+    center: Coordinates,
+}
+
+/// Parses a cell id, guessing the encoding when none is given: 64 binary digits, then a
+/// decimal number, then a hexadecimal S2 token.
+pub fn parse_cell_id(input: &str, encoding: Option<&Encoding>) -> Result<CellID, AppError> {
+    let encoding = match encoding {
+        Some(encoding) => encoding,
+        None if input.len() == 64 && input.chars().all(|c| c == '0' || c == '1') => &Encoding::Binary,
+        None if input.chars().all(|c| c.is_ascii_digit()) => &Encoding::Decimal,
+        None => &Encoding::Token,
+    };
+
+    let cell_id = match encoding {
+        Encoding::Binary => u64::from_str_radix(input, 2).ok(),
+        Encoding::Decimal => input.parse::<u64>().ok(),
+        Encoding::Token => (input.len() <= 16 && u64::from_str_radix(input, 16).is_ok())
+            .then(|| CellID::from_token(input).0),
+    };
+
+    cell_id
+        .map(CellID)
+        .filter(CellID::is_valid)
+        .ok_or_else(|| AppError::BadRequest(format!("invalid cell id: {}", input)))
+}
+
+#[get("/21/cell/{id}")]
+async fn cell_info(id: web::Path<String>, params: web::Query<CellParams>) -> Result<HttpResponse, AppError> {
+    let cell_id = parse_cell_id(&id, params.encoding.as_ref())?;
+
+    Ok(HttpResponse::Ok().json(CellInfo {
+        cell: cell_id.into(),
+        center: Cell::from(cell_id).center().into(),
+    }))
+}
+
+#[get("/21/cell/{id}/parent")]
+async fn cell_parent(id: web::Path<String>, params: web::Query<CellParams>) -> Result<HttpResponse, AppError> {
+    let cell_id = parse_cell_id(&id, params.encoding.as_ref())?;
+
+    let level = match params.level {
+        Some(level) if level > cell_id.level() => {
+            return Err(AppError::BadRequest(format!(
+                "parent level {} is deeper than the cell level {}",
+                level,
+                cell_id.level()
+            )));
+        }
+        Some(level) => level,
+        None if cell_id.is_face() => return Err(AppError::bad_request("a face cell has no parent")),
+        None => cell_id.level() - 1,
+    };
+
+    Ok(HttpResponse::Ok().json(CellSummary::from(cell_id.parent(level))))
+}
+
+#[get("/21/cell/{id}/children")]
+async fn cell_children(id: web::Path<String>, params: web::Query<CellParams>) -> Result<HttpResponse, AppError> {
+    let cell_id = parse_cell_id(&id, params.encoding.as_ref())?;
+
+    if cell_id.is_leaf() {
+        return Err(AppError::bad_request("a leaf cell has no children"));
+    }
+
+    let children: Vec<CellSummary> = cell_id.children().into_iter().map(CellSummary::from).collect();
+    Ok(HttpResponse::Ok().json(children))
+}
+
+#[get("/21/cell/{id}/neighbors")]
+async fn cell_neighbors(id: web::Path<String>, params: web::Query<CellParams>) -> Result<HttpResponse, AppError> {
+    let cell_id = parse_cell_id(&id, params.encoding.as_ref())?;
+
+    let neighbors: Vec<CellSummary> = cell_id.edge_neighbors().into_iter().map(CellSummary::from).collect();
+    Ok(HttpResponse::Ok().json(neighbors))
+}
+
+#[get("/21/cell/{id}/vertices")]
+async fn cell_vertices(id: web::Path<String>, params: web::Query<CellParams>) -> Result<HttpResponse, AppError> {
+    let cell_id = parse_cell_id(&id, params.encoding.as_ref())?;
+
+    let vertices: Vec<Coordinates> = Cell::from(cell_id).vertices().into_iter().map(Coordinates::from).collect();
+    Ok(HttpResponse::Ok().json(vertices))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{body, test, App};
+
+    use super::CellSummary;
+
+    const MADAGASCAR: &str = "0010000111110000011111100000111010111100000100111101111011000101";
+
+    #[actix_web::test]
+    async fn test_cell_info() {
+        let app = test::init_service(App::new().configure(super::configure)).await;
+
+        let req = test::TestRequest::get().uri(&format!("/21/cell/{}", MADAGASCAR)).to_request();
+
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+
+        let body = resp.into_body();
+        let bytes = body::to_bytes(body).await.unwrap();
+        let response: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+
+        assert_eq!(response["token"], "21f07e0ebc13dec5");
+        assert_eq!(response["level"], 30);
+        assert_eq!(response["face"], 1);
+        assert_eq!(response["binary"], MADAGASCAR);
+    }
+
+    #[actix_web::test]
+    async fn test_cell_encodings() {
+        let app = test::init_service(App::new().configure(super::configure)).await;
+
+        for id in ["21f07e0ebc13dec5", "2445593199412240069"] {
+            let req = test::TestRequest::get().uri(&format!("/21/cell/{}/parent?level=10", id)).to_request();
+
+            let resp = test::call_service(&app, req).await;
+
+            assert!(resp.status().is_success());
+
+            let body = resp.into_body();
+            let bytes = body::to_bytes(body).await.unwrap();
+            let response: CellSummary = serde_json::from_slice(&bytes).unwrap();
+
+            assert_eq!(response.level, 10);
+            assert_eq!(response.token, "21f07f");
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_cell_children_and_neighbors() {
+        let app = test::init_service(App::new().configure(super::configure)).await;
+
+        let req = test::TestRequest::get().uri("/21/cell/21f07f/children").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+        let children: Vec<CellSummary> = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(children.len(), 4);
+        assert!(children.iter().all(|child| child.level == 11 && child.token.starts_with("21f07")));
+
+        let req = test::TestRequest::get().uri("/21/cell/21f07f/neighbors").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+        let neighbors: Vec<CellSummary> = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(neighbors.len(), 4);
+        assert!(neighbors.iter().all(|neighbor| neighbor.level == 10 && neighbor.token != "21f07f"));
+    }
+
+    #[actix_web::test]
+    async fn test_cell_vertices() {
+        let app = test::init_service(App::new().configure(super::configure)).await;
+
+        let req = test::TestRequest::get().uri("/21/cell/21f07f/vertices").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+        let vertices: Vec<super::Coordinates> = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(vertices.len(), 4);
+        assert!(vertices.iter().all(|v| (v.lat + 18.9).abs() < 0.2 && (v.lng - 47.5).abs() < 0.2));
+    }
+
+    #[actix_web::test]
+    async fn test_invalid_cell() {
+        let app = test::init_service(App::new().configure(super::configure)).await;
+
+        let req = test::TestRequest::get().uri("/21/cell/zz").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::get().uri("/21/cell/1/children").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+}
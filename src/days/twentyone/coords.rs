@@ -0,0 +1,138 @@
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+const GEOHASH_ALPHABET: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+const PLUS_CODE_ALPHABET: &[u8] = b"23456789CFGHJMPQRVWX";
+// A 10-digit plus code resolves to 1/8000th of a degree on both axes.
+const PLUS_CODE_RESOLUTION: f64 = 8000.0;
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Dms,
+    Ddm,
+    Decimal,
+    Geojson,
+    Geohash,
+    Pluscode,
+}
+
+fn hemisphere(value: f64, positive: &'static str, negative: &'static str) -> &'static str {
+    // 0° (and -0.0) counts as north/east
+    if value >= 0.0 { positive } else { negative }
+}
+
+/// Degrees, minutes and seconds with the seconds rounded to milliseconds, carrying into the
+/// minutes and degrees so a value never renders as 60 seconds.
+pub fn dms(value: f64, positive: &'static str, negative: &'static str) -> String {
+    let millis = (value.abs() * 3_600_000.0).round() as u64;
+    format!(
+        "{}°{}'{}.{:03}''{}",
+        millis / 3_600_000,
+        millis % 3_600_000 / 60_000,
+        millis % 60_000 / 1000,
+        millis % 1000,
+        hemisphere(value, positive, negative)
+    )
+}
+
+/// Degrees and decimal minutes, with the minutes rounded to three decimals.
+pub fn ddm(value: f64, positive: &'static str, negative: &'static str) -> String {
+    let thousandths = (value.abs() * 60_000.0).round() as u64;
+    format!(
+        "{}°{}.{:03}'{}",
+        thousandths / 60_000,
+        thousandths % 60_000 / 1000,
+        thousandths % 1000,
+        hemisphere(value, positive, negative)
+    )
+}
+
+pub fn geojson(latitude: f64, longitude: f64) -> Value {
+    json!({ "type": "Point", "coordinates": [longitude, latitude] })
+}
+
+pub fn geohash(latitude: f64, longitude: f64, precision: usize) -> String {
+    let mut lat_range = (-90.0, 90.0);
+    let mut lng_range = (-180.0, 180.0);
+    let mut hash = String::with_capacity(precision);
+    let mut even = true;
+    let mut bits = 0;
+    let mut index = 0;
+
+    while hash.len() < precision {
+        let (range, value) = if even { (&mut lng_range, longitude) } else { (&mut lat_range, latitude) };
+        let mid = (range.0 + range.1) / 2.0;
+        index <<= 1;
+        if value >= mid {
+            index |= 1;
+            range.0 = mid;
+        } else {
+            range.1 = mid;
+        }
+        even = !even;
+
+        bits += 1;
+        if bits == 5 {
+            hash.push(GEOHASH_ALPHABET[index] as char);
+            bits = 0;
+            index = 0;
+        }
+    }
+
+    hash
+}
+
+/// A 10-digit Open Location Code such as `8FVC9G8F+6X`.
+pub fn plus_code(latitude: f64, longitude: f64) -> String {
+    let max_lat = (180.0 * PLUS_CODE_RESOLUTION) as u64 - 1;
+    let max_lng = (360.0 * PLUS_CODE_RESOLUTION) as u64;
+    let mut lat = (((latitude.clamp(-90.0, 90.0) + 90.0) * PLUS_CODE_RESOLUTION).floor() as u64).min(max_lat);
+    let mut lng = ((longitude + 180.0).rem_euclid(360.0) * PLUS_CODE_RESOLUTION).floor() as u64 % max_lng;
+
+    let mut pairs = Vec::with_capacity(5);
+    for _ in 0..5 {
+        pairs.push((PLUS_CODE_ALPHABET[(lat % 20) as usize], PLUS_CODE_ALPHABET[(lng % 20) as usize]));
+        lat /= 20;
+        lng /= 20;
+    }
+
+    let mut code = String::with_capacity(11);
+    for (i, (lat_digit, lng_digit)) in pairs.into_iter().rev().enumerate() {
+        if i == 4 {
+            code.push('+');
+        }
+        code.push(lat_digit as char);
+        code.push(lng_digit as char);
+    }
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_dms_hemispheres() {
+        assert_eq!(super::dms(0.0, "N", "S"), "0°0'0.000''N");
+        assert_eq!(super::dms(-0.0, "E", "W"), "0°0'0.000''E");
+        assert_eq!(super::dms(-179.5, "E", "W"), "179°30'0.000''W");
+        // 59.9999 seconds rounds up into the next minute
+        assert_eq!(super::dms(10.0 + 59.9999 / 3600.0, "N", "S"), "10°1'0.000''N");
+    }
+
+    #[test]
+    fn test_ddm() {
+        assert_eq!(super::ddm(-18.915539982809292, "N", "S"), "18°54.932'S");
+    }
+
+    #[test]
+    fn test_geohash() {
+        assert_eq!(super::geohash(57.64911, 10.40744, 11), "u4pruydqqvj");
+    }
+
+    #[test]
+    fn test_plus_code() {
+        assert_eq!(super::plus_code(47.0000625, 8.0000625), "8FVC2222+22");
+        assert_eq!(super::plus_code(-41.2730625, 174.7859375), "4VCPPQGP+Q9");
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use google_maps::{GoogleMapsClient, LatLng, PlaceType};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::error::AppError;
+
+#[async_trait]
+pub trait CountryResolver: Send + Sync {
+    async fn country(&self, latitude: f64, longitude: f64) -> Result<String, AppError>;
+}
+
+/// Builds the resolver from the environment. `COUNTRY_RESOLVER` picks `google` (reverse
+/// geocoding with `GOOGLE_API_KEY`) or `offline` (the GeoJSON boundaries in
+/// `COUNTRY_BOUNDARIES`, e.g. Natural Earth's 110m admin 0 countries); when unset, whichever of
+/// the two is configured is used, Google first. With neither, lookups fail until one is.
+pub fn from_env() -> Result<Arc<dyn CountryResolver>, AppError> {
+    dotenv::dotenv().ok();
+
+    let api_key = std::env::var("GOOGLE_API_KEY").ok();
+    let boundaries = std::env::var("COUNTRY_BOUNDARIES").ok();
+    let resolver = match std::env::var("COUNTRY_RESOLVER") {
+        Ok(resolver) => resolver,
+        Err(_) if api_key.is_some() => "google".to_string(),
+        Err(_) if boundaries.is_some() => "offline".to_string(),
+        Err(_) => {
+            tracing::warn!("neither GOOGLE_API_KEY nor COUNTRY_BOUNDARIES is set, countries cannot be looked up");
+            return Ok(Arc::new(UnconfiguredCountryResolver));
+        }
+    };
+
+    match resolver.as_str() {
+        "google" => {
+            let api_key = api_key.ok_or_else(|| AppError::internal("GOOGLE_API_KEY is required for the google country resolver"))?;
+            Ok(Arc::new(GoogleCountryResolver::new(&api_key)))
+        }
+        "offline" => {
+            let path = boundaries.ok_or_else(|| AppError::internal("COUNTRY_BOUNDARIES is required for the offline country resolver"))?;
+            let resolver = OfflineCountryResolver::from_file(&path)
+                .map_err(|err| AppError::Internal(format!("could not load country boundaries from {}: {}", path, err)))?;
+            Ok(Arc::new(resolver))
+        }
+        other => Err(AppError::Internal(format!("unknown country resolver: {}", other))),
+    }
+}
+
+/// Fails every lookup, as no source of country boundaries was configured.
+pub struct UnconfiguredCountryResolver;
+
+#[async_trait]
+impl CountryResolver for UnconfiguredCountryResolver {
+    async fn country(&self, _latitude: f64, _longitude: f64) -> Result<String, AppError> {
+        Err(AppError::internal("no country resolver is configured, set GOOGLE_API_KEY or COUNTRY_BOUNDARIES"))
+    }
+}
+
+pub struct GoogleCountryResolver {
+    client: GoogleMapsClient,
+}
+
+impl GoogleCountryResolver {
+    pub fn new(api_key: &str) -> Self {
+        GoogleCountryResolver {
+            client: GoogleMapsClient::new(api_key),
+        }
+    }
+}
+
+#[async_trait]
+impl CountryResolver for GoogleCountryResolver {
+    async fn country(&self, latitude: f64, longitude: f64) -> Result<String, AppError> {
+        let location = self.client.reverse_geocoding(
+            LatLng::try_from_f64(latitude, longitude).map_err(AppError::bad_request)?
+        )
+            .with_result_type(PlaceType::Country)
+            .execute()
+            .await
+            .map_err(AppError::upstream)?;
+
+        location.results
+            .first()
+            .and_then(|result| result.address_components.first())
+            .map(|component| component.long_name.clone())
+            .ok_or_else(|| AppError::not_found("no country found for cell"))
+    }
+}
+
+#[derive(Deserialize)]
+struct FeatureCollection {
+    features: Vec<Feature>,
+}
+
+#[derive(Deserialize)]
+struct Feature {
+    properties: HashMap<String, Value>,
+    geometry: Geometry,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", content = "coordinates")]
+enum Geometry {
+    Polygon(Vec<Vec<[f64; 2]>>),
+    MultiPolygon(Vec<Vec<Vec<[f64; 2]>>>),
+}
+
+/// A polygon as a list of `[longitude, latitude]` rings, the first being the outer boundary and
+/// the rest holes, as in GeoJSON.
+struct Polygon {
+    rings: Vec<Vec<[f64; 2]>>,
+    bounds: [f64; 4],
+}
+
+impl Polygon {
+    fn new(rings: Vec<Vec<[f64; 2]>>) -> Self {
+        let mut bounds = [f64::MAX, f64::MAX, f64::MIN, f64::MIN];
+        for &[lng, lat] in rings.iter().take(1).flatten() {
+            bounds = [bounds[0].min(lng), bounds[1].min(lat), bounds[2].max(lng), bounds[3].max(lat)];
+        }
+        Polygon { rings, bounds }
+    }
+
+    fn contains(&self, latitude: f64, longitude: f64) -> bool {
+        let [min_lng, min_lat, max_lng, max_lat] = self.bounds;
+        if longitude < min_lng || longitude > max_lng || latitude < min_lat || latitude > max_lat {
+            return false;
+        }
+
+        // even-odd rule over all rings, so a point inside a hole is outside the polygon
+        self.rings
+            .iter()
+            .filter(|ring| ring_contains(ring, latitude, longitude))
+            .count() % 2 == 1
+    }
+}
+
+fn ring_contains(ring: &[[f64; 2]], latitude: f64, longitude: f64) -> bool {
+    let mut inside = false;
+    let mut j = ring.len().wrapping_sub(1);
+    for i in 0..ring.len() {
+        let [xi, yi] = ring[i];
+        let [xj, yj] = ring[j];
+        if (yi > latitude) != (yj > latitude) && longitude < (xj - xi) * (latitude - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+/// Point-in-polygon lookup against a GeoJSON `FeatureCollection` of country boundaries. The
+/// country name is read from the `name`, `NAME` or `ADMIN` property, so Natural Earth exports
+/// can be used as-is.
+pub struct OfflineCountryResolver {
+    countries: Vec<(String, Vec<Polygon>)>,
+}
+
+impl OfflineCountryResolver {
+    pub fn from_geojson(geojson: &str) -> Result<Self, AppError> {
+        let collection: FeatureCollection = serde_json::from_str(geojson).map_err(AppError::internal)?;
+
+        let countries = collection.features
+            .into_iter()
+            .map(|feature| {
+                let name = ["name", "NAME", "ADMIN"]
+                    .iter()
+                    .find_map(|key| feature.properties.get(*key).and_then(Value::as_str))
+                    .ok_or_else(|| AppError::internal("country feature without a name"))?
+                    .to_string();
+                let polygons = match feature.geometry {
+                    Geometry::Polygon(rings) => vec![Polygon::new(rings)],
+                    Geometry::MultiPolygon(polygons) => polygons.into_iter().map(Polygon::new).collect(),
+                };
+                Ok((name, polygons))
+            })
+            .collect::<Result<Vec<_>, AppError>>()?;
+
+        Ok(OfflineCountryResolver { countries })
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, AppError> {
+        let contents = std::fs::read_to_string(path).map_err(AppError::internal)?;
+        OfflineCountryResolver::from_geojson(&contents)
+    }
+}
+
+#[async_trait]
+impl CountryResolver for OfflineCountryResolver {
+    async fn country(&self, latitude: f64, longitude: f64) -> Result<String, AppError> {
+        self.countries
+            .iter()
+            .find(|(_, polygons)| polygons.iter().any(|polygon| polygon.contains(latitude, longitude)))
+            .map(|(name, _)| name.clone())
+            .ok_or_else(|| AppError::not_found("no country found for cell"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CountryResolver, OfflineCountryResolver};
+    use crate::error::AppError;
+
+    const SQUARE_WITH_HOLE: &str = r#"{
+        "type": "FeatureCollection",
+        "features": [
+            {
+                "type": "Feature",
+                "properties": { "name": "Squareland" },
+                "geometry": {
+                    "type": "Polygon",
+                    "coordinates": [
+                        [[0, 0], [10, 0], [10, 10], [0, 10], [0, 0]],
+                        [[4, 4], [6, 4], [6, 6], [4, 6], [4, 4]]
+                    ]
+                }
+            },
+            {
+                "type": "Feature",
+                "properties": { "NAME": "Holeland" },
+                "geometry": {
+                    "type": "MultiPolygon",
+                    "coordinates": [[[[4.5, 4.5], [5.5, 4.5], [5.5, 5.5], [4.5, 5.5], [4.5, 4.5]]]]
+                }
+            }
+        ]
+    }"#;
+
+    #[actix_web::test]
+    async fn test_point_in_polygon() {
+        let resolver = OfflineCountryResolver::from_geojson(SQUARE_WITH_HOLE).unwrap();
+
+        assert_eq!(resolver.country(1.0, 1.0).await.unwrap(), "Squareland");
+        assert_eq!(resolver.country(5.0, 5.0).await.unwrap(), "Holeland");
+        assert!(resolver.country(4.2, 4.2).await.is_err());
+        assert!(resolver.country(-1.0, 5.0).await.is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_open_ocean() {
+        let resolver = OfflineCountryResolver::from_file("assets/fixtures/countries.geojson").unwrap();
+
+        // the middle of the South Atlantic, and the Indian Ocean just off Madagascar
+        for (latitude, longitude) in [(-30.0, -15.0), (-20.0, 52.0)] {
+            assert!(matches!(resolver.country(latitude, longitude).await, Err(AppError::NotFound(_))));
+        }
+        assert_eq!(resolver.country(-20.0, 46.0).await.unwrap(), "Madagascar");
+    }
+}
@@ -0,0 +1,195 @@
+use actix_web::{post, web, HttpResponse};
+use s2::cap::Cap;
+use s2::cellid::CellID;
+use s2::latlng::LatLng;
+use s2::point::Point;
+use s2::rect::Rect;
+use s2::region::{Region, RegionCoverer};
+use s2::s1::{Angle, Rad};
+use serde::{Deserialize, Serialize};
+
+use crate::error::AppError;
+
+use super::cell::CellSummary;
+
+const EARTH_RADIUS_METERS: f64 = 6_371_010.0;
+const MAX_LEVEL: u8 = 30;
+const MAX_CELLS_LIMIT: usize = 1000;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(covering);
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Shape {
+    /// A latitude/longitude rectangle; `lng_lo > lng_hi` wraps around the antimeridian.
+    Rect {
+        lat_lo: f64,
+        lng_lo: f64,
+        lat_hi: f64,
+        lng_hi: f64,
+    },
+    Cap {
+        lat: f64,
+        lng: f64,
+        radius_meters: f64,
+    },
+}
+
+#[derive(Deserialize)]
+struct CoveringRequest {
+    region: Shape,
+    min_level: Option<u8>,
+    max_level: Option<u8>,
+    max_cells: Option<usize>,
+    #[serde(default)]
+    interior: bool,
+}
+
+#[derive(Serialize)]
+struct CoveringResponse {
+    covering: Vec<CellSummary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    interior: Option<Vec<CellSummary>>,
+}
+
+fn check_latlng(lat: f64, lng: f64) -> Result<(), AppError> {
+    if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lng) {
+        return Err(AppError::BadRequest(format!("invalid coordinates: {}, {}", lat, lng)));
+    }
+    Ok(())
+}
+
+fn to_summaries(cells: Vec<CellID>) -> Vec<CellSummary> {
+    cells.into_iter().map(CellSummary::from).collect()
+}
+
+fn cover<R: Region + 'static>(coverer: &RegionCoverer, region: &R, interior: bool) -> CoveringResponse {
+    CoveringResponse {
+        covering: to_summaries(coverer.covering(region).0),
+        interior: interior.then(|| to_summaries(coverer.interior_covering(region).0)),
+    }
+}
+
+#[post("/21/covering")]
+async fn covering(request: web::Json<CoveringRequest>) -> Result<HttpResponse, AppError> {
+    let request = request.into_inner();
+
+    let min_level = request.min_level.unwrap_or(0);
+    let max_level = request.max_level.unwrap_or(MAX_LEVEL);
+    let max_cells = request.max_cells.unwrap_or(8);
+    if min_level > max_level || max_level > MAX_LEVEL {
+        return Err(AppError::BadRequest(format!("invalid level range: {}..={}", min_level, max_level)));
+    }
+    if max_cells == 0 || max_cells > MAX_CELLS_LIMIT {
+        return Err(AppError::BadRequest(format!("max_cells must be between 1 and {}", MAX_CELLS_LIMIT)));
+    }
+
+    let coverer = RegionCoverer {
+        min_level,
+        max_level,
+        level_mod: 1,
+        max_cells,
+    };
+
+    let response = match request.region {
+        Shape::Rect { lat_lo, lng_lo, lat_hi, lng_hi } => {
+            check_latlng(lat_lo, lng_lo)?;
+            check_latlng(lat_hi, lng_hi)?;
+            if lat_lo > lat_hi {
+                return Err(AppError::bad_request("lat_lo must not be greater than lat_hi"));
+            }
+            cover(&coverer, &Rect::from_degrees(lat_lo, lng_lo, lat_hi, lng_hi), request.interior)
+        }
+        Shape::Cap { lat, lng, radius_meters } => {
+            check_latlng(lat, lng)?;
+            if !radius_meters.is_finite() || radius_meters <= 0.0 {
+                return Err(AppError::BadRequest(format!("invalid radius: {}", radius_meters)));
+            }
+            let center = Point::from(LatLng::from_degrees(lat, lng));
+            let angle = Angle::from(Rad(radius_meters / EARTH_RADIUS_METERS));
+            cover(&coverer, &Cap::from_center_angle(&center, &angle), request.interior)
+        }
+    };
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{body, test, App};
+    use serde_json::json;
+
+    #[actix_web::test]
+    async fn test_rect_covering() {
+        let app = test::init_service(App::new().configure(super::configure)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/21/covering")
+            .set_json(json!({
+                "region": { "type": "rect", "lat_lo": -19.0, "lng_lo": 47.4, "lat_hi": -18.8, "lng_hi": 47.6 },
+                "min_level": 8,
+                "max_level": 12,
+                "max_cells": 10,
+                "interior": true
+            }))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+
+        let body = resp.into_body();
+        let bytes = body::to_bytes(body).await.unwrap();
+        let response: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+
+        let covering = response["covering"].as_array().unwrap();
+        assert!(!covering.is_empty() && covering.len() <= 10);
+        assert!(covering.iter().all(|cell| (8..=12).contains(&cell["level"].as_u64().unwrap())));
+        assert!(response["interior"].is_array());
+    }
+
+    #[actix_web::test]
+    async fn test_cap_covering() {
+        let app = test::init_service(App::new().configure(super::configure)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/21/covering")
+            .set_json(json!({
+                "region": { "type": "cap", "lat": 51.0, "lng": 4.4, "radius_meters": 1000.0 },
+                "max_cells": 4
+            }))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+
+        let body = resp.into_body();
+        let bytes = body::to_bytes(body).await.unwrap();
+        let response: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+
+        let covering = response["covering"].as_array().unwrap();
+        assert!(!covering.is_empty() && covering.len() <= 4);
+        assert!(response.get("interior").is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_invalid_covering() {
+        let app = test::init_service(App::new().configure(super::configure)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/21/covering")
+            .set_json(json!({
+                "region": { "type": "cap", "lat": 51.0, "lng": 4.4, "radius_meters": 1000.0 },
+                "min_level": 12,
+                "max_level": 8
+            }))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+}
@@ -1,9 +1,23 @@
-use actix_web::{get, Responder, web};
-use google_maps::{GoogleMapsClient, LatLng, PlaceType};
+use actix_web::{get, web, HttpResponse};
+use serde::Deserialize;
 
+use crate::error::AppError;
+
+use self::coords::Format;
+use self::country::CountryResolver;
+
+pub mod cell;
+pub mod coords;
+pub mod country;
+pub mod covering;
+
+/// Expects a [`CountryResolver`] to be registered as app data; it is built once in `main`, as
+/// loading the boundaries is expensive.
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(part_1);
     cfg.service(part_2);
+    cfg.configure(cell::configure);
+    cfg.configure(covering::configure);
 }
 
 fn get_coords(cell_id: u64) -> (f64, f64) {
@@ -16,47 +30,156 @@ fn get_coords(cell_id: u64) -> (f64, f64) {
     (latitude, longitude)
 }
 
+fn parse_cell_id(binary: &str) -> Result<u64, AppError> {
+    u64::from_str_radix(binary, 2)
+        .ok()
+        .filter(|&cell_id| s2::cellid::CellID(cell_id).is_valid())
+        .ok_or_else(|| AppError::BadRequest(format!("invalid binary cell id: {}", binary)))
+}
+
+#[derive(Deserialize)]
+struct CoordsParams {
+    #[serde(default)]
+    format: Format,
+    precision: Option<usize>,
+}
+
 #[get("/21/coords/{binary}")]
 async fn part_1(
     binary: web::Path<String>,
-) -> impl Responder {
-    let cell_id = u64::from_str_radix(&binary, 2).unwrap();
+    params: web::Query<CoordsParams>,
+) -> Result<HttpResponse, AppError> {
+    let cell_id = parse_cell_id(&binary)?;
     let (latitude, longitude) = get_coords(cell_id);
-    let latitude = format!("{}°{}'{:.3}''{}",
-                           latitude.abs() as u8,
-                           (latitude.fract() * 60.0).abs() as u8,
-                           ((latitude.fract() * 60.0).fract() * 60.0).abs(),
-                           if latitude > 0.0 { "N" } else { "S" }
-    );
-    let longitude = format!("{}°{}'{:.3}''{}",
-                            longitude.abs() as u8,
-                            (longitude.fract() * 60.0).abs() as u8,
-                            ((longitude.fract() * 60.0).fract() * 60.0).abs(),
-                            if longitude > 0.0 { "E" } else { "W" });
-
-    format!("{} {}", latitude, longitude)
+
+    let body = match params.format {
+        Format::Dms => format!("{} {}", coords::dms(latitude, "N", "S"), coords::dms(longitude, "E", "W")),
+        Format::Ddm => format!("{} {}", coords::ddm(latitude, "N", "S"), coords::ddm(longitude, "E", "W")),
+        Format::Decimal => format!("{:.6} {:.6}", latitude, longitude),
+        Format::Geojson => return Ok(HttpResponse::Ok().json(coords::geojson(latitude, longitude))),
+        Format::Geohash => {
+            let precision = params.precision.unwrap_or(12);
+            if !(1..=12).contains(&precision) {
+                return Err(AppError::BadRequest(format!("invalid geohash precision: {}", precision)));
+            }
+            coords::geohash(latitude, longitude, precision)
+        }
+        Format::Pluscode => coords::plus_code(latitude, longitude),
+    };
+
+    Ok(HttpResponse::Ok().body(body))
 }
 
 #[get("/21/country/{binary}")]
 async fn part_2(
     binary: web::Path<String>,
-) -> impl Responder {
-    dotenv::dotenv().ok();
-    let api_key = match std::env::var("GOOGLE_API_KEY") {
-        Ok(key) => key,
-        Err(_) => panic!("No API key found")
-    };
-    let google_maps_client = GoogleMapsClient::new(api_key.as_str());
-
-    let cell_id = u64::from_str_radix(&binary, 2).unwrap();
+    resolver: web::Data<dyn CountryResolver>,
+) -> Result<String, AppError> {
+    let cell_id = parse_cell_id(&binary)?;
     let (latitude, longitude) = get_coords(cell_id);
 
-    let location = google_maps_client.reverse_geocoding(
-        LatLng::try_from_f64(latitude, longitude).unwrap()
-    )
-        .with_result_type(PlaceType::Country)
-        .execute()
-        .await
-        .unwrap();
-    location.results[0].address_components[0].long_name.clone()
+    resolver.country(latitude, longitude).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{body, test, web, App};
+
+    use super::country::{CountryResolver, OfflineCountryResolver};
+
+    fn configure(cfg: &mut web::ServiceConfig) {
+        // coarse outlines of just the countries the examples land in
+        let resolver: Arc<dyn CountryResolver> =
+            Arc::new(OfflineCountryResolver::from_file("assets/fixtures/countries.geojson").unwrap());
+        cfg.app_data(web::Data::from(resolver));
+        super::configure(cfg);
+    }
+
+    #[actix_web::test]
+    async fn test_part_1() {
+        let app = test::init_service(App::new().configure(configure)).await;
+
+        let req = test::TestRequest::get()
+            .uri("/21/coords/0100111110010011000110011001010101011111000010100011110001011011")
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+
+        let body = resp.into_body();
+        let bytes = body::to_bytes(body).await.unwrap();
+        assert_eq!(bytes, "83°39'54.324''N 30°37'40.584''W");
+    }
+
+    #[actix_web::test]
+    async fn test_part_2() {
+        let app = test::init_service(App::new().configure(configure)).await;
+
+        let req = test::TestRequest::get()
+            .uri("/21/country/0010000111110000011111100000111010111100000100111101111011000101")
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+
+        let body = resp.into_body();
+        let bytes = body::to_bytes(body).await.unwrap();
+        assert_eq!(bytes, "Madagascar");
+    }
+
+    #[actix_web::test]
+    async fn test_part_2_ocean() {
+        let app = test::init_service(App::new().configure(configure)).await;
+
+        // the face cell centred on 0°N 180°E, in the middle of the Pacific Ocean
+        let req = test::TestRequest::get()
+            .uri("/21/country/0111000000000000000000000000000000000000000000000000000000000000")
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_part_1_formats() {
+        let app = test::init_service(App::new().configure(configure)).await;
+
+        let cell = "0010000111110000011111100000111010111100000100111101111011000101";
+        for (format, expected) in [
+            ("dms", "18°54'55.944''S 47°31'17.976''E"),
+            ("ddm", "18°54.932'S 47°31.300'E"),
+            ("decimal", "-18.915540 47.521660"),
+            ("geohash&precision=7", "mh9u3ks"),
+            ("pluscode", "5HH93GMC+QM"),
+        ] {
+            let req = test::TestRequest::get()
+                .uri(&format!("/21/coords/{}?format={}", cell, format))
+                .to_request();
+
+            let resp = test::call_service(&app, req).await;
+
+            assert!(resp.status().is_success());
+
+            let body = resp.into_body();
+            let bytes = body::to_bytes(body).await.unwrap();
+            assert_eq!(bytes, expected, "format {}", format);
+        }
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/21/coords/{}?format=geojson", cell))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+
+        let body = resp.into_body();
+        let bytes = body::to_bytes(body).await.unwrap();
+        let response: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(response["type"], "Point");
+        assert_eq!(response["coordinates"][0], 47.5216600194372);
+    }
 }
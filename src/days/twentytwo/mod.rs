@@ -1,14 +1,223 @@
-use actix_web::{post, Responder, web};
+use std::collections::VecDeque;
 
-pub fn configure(cfg: &mut web::ServiceConfig) {}
+use actix_web::{post, web};
 
+use crate::error::AppError;
 
-#[post("/22")]
-async fn part_1(string: String) -> impl Responder {
-    string
+// Each present is 4 bytes, so this keeps the response to a few megabytes.
+const MAX_PRESENTS: u64 = 1 << 20;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(part_1);
+    cfg.service(part_2);
+}
+
+#[post("/22/integers")]
+async fn part_1(string: String) -> Result<String, AppError> {
+    let unpaired = string
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            line.trim()
+                .parse::<u64>()
+                .map_err(|_| AppError::BadRequest(format!("invalid integer: {}", line)))
+        })
+        .try_fold(0, |acc, num| num.map(|num| acc ^ num))?;
+
+    if unpaired > MAX_PRESENTS {
+        return Err(AppError::BadRequest(format!(
+            "too many presents: {}, at most {} are allowed",
+            unpaired, MAX_PRESENTS
+        )));
+    }
+    Ok("🎁".repeat(unpaired as usize))
+}
+
+struct Star {
+    x: f64,
+    y: f64,
+    z: f64,
+}
+
+impl Star {
+    fn distance(&self, other: &Star) -> f64 {
+        ((self.x - other.x).powi(2) + (self.y - other.y).powi(2) + (self.z - other.z).powi(2)).sqrt()
+    }
+}
+
+fn next_line<'a>(lines: &mut impl Iterator<Item = &'a str>) -> Result<&'a str, AppError> {
+    lines
+        .next()
+        .ok_or_else(|| AppError::bad_request("unexpected end of input"))
+}
+
+fn parse_numbers<T: std::str::FromStr>(line: &str, count: usize) -> Result<Vec<T>, AppError> {
+    let numbers = line
+        .split_whitespace()
+        .map(|n| n.parse::<T>().map_err(|_| AppError::BadRequest(format!("invalid number: {}", n))))
+        .collect::<Result<Vec<T>, _>>()?;
+
+    if numbers.len() != count {
+        return Err(AppError::BadRequest(format!("expected {} numbers, got: {}", count, line)));
+    }
+    Ok(numbers)
+}
+
+fn parse_rocket(input: &str) -> Result<(Vec<Star>, Vec<Vec<usize>>), AppError> {
+    let mut lines = input.lines().filter(|line| !line.trim().is_empty());
+
+    let star_count = parse_numbers::<usize>(next_line(&mut lines)?, 1)?[0];
+    let stars = (0..star_count)
+        .map(|_| {
+            let coords = parse_numbers::<i32>(next_line(&mut lines)?, 3)?;
+            Ok(Star { x: coords[0] as f64, y: coords[1] as f64, z: coords[2] as f64 })
+        })
+        .collect::<Result<Vec<Star>, AppError>>()?;
+
+    let portal_count = parse_numbers::<usize>(next_line(&mut lines)?, 1)?[0];
+    let mut portals = vec![Vec::new(); star_count];
+    for _ in 0..portal_count {
+        let portal = parse_numbers::<usize>(next_line(&mut lines)?, 2)?;
+        if portal[0] >= star_count || portal[1] >= star_count {
+            return Err(AppError::BadRequest(format!("portal to unknown star: {} {}", portal[0], portal[1])));
+        }
+        portals[portal[0]].push(portal[1]);
+    }
+
+    Ok((stars, portals))
+}
+
+/// Breadth-first search from the first to the last star, returning the path with the fewest portals.
+fn shortest_path(portals: &[Vec<usize>]) -> Option<Vec<usize>> {
+    let target = portals.len().checked_sub(1)?;
+    let mut previous = vec![None; portals.len()];
+    let mut visited = vec![false; portals.len()];
+    let mut queue = VecDeque::from([0]);
+    visited[0] = true;
+
+    while let Some(star) = queue.pop_front() {
+        if star == target {
+            let mut path = vec![star];
+            while let Some(prev) = previous[*path.last().unwrap()] {
+                path.push(prev);
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        for &next in &portals[star] {
+            if !visited[next] {
+                visited[next] = true;
+                previous[next] = Some(star);
+                queue.push_back(next);
+            }
+        }
+    }
+
+    None
+}
+
+#[post("/22/rocket")]
+async fn part_2(string: String) -> Result<String, AppError> {
+    let (stars, portals) = parse_rocket(&string)?;
+    let path = shortest_path(&portals)
+        .ok_or_else(|| AppError::not_found("no route to the last star"))?;
+
+    let distance: f64 = path
+        .windows(2)
+        .map(|pair| stars[pair[0]].distance(&stars[pair[1]]))
+        .sum();
+
+    Ok(format!("{} {:.3}", path.len() - 1, distance))
 }
 
-#[post("/22")]
-async fn part_2(string: String) -> impl Responder {
-    string
+#[cfg(test)]
+mod tests {
+    use actix_web::http::header::ContentType;
+    use actix_web::{body, test, App};
+
+    #[actix_web::test]
+    async fn test_part_1() {
+        let app = test::init_service(App::new().configure(super::configure)).await;
+
+        let body = "888\n77\n888\n22\n77\n";
+
+        let req = test::TestRequest::post()
+            .uri("/22/integers")
+            .insert_header(ContentType::plaintext())
+            .set_payload(body)
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+
+        let body = resp.into_body();
+        let bytes = body::to_bytes(body).await.unwrap();
+        assert_eq!(bytes, "🎁".repeat(22));
+    }
+
+    #[actix_web::test]
+    async fn test_part_1_too_many_presents() {
+        let app = test::init_service(App::new().configure(super::configure)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/22/integers")
+            .insert_header(ContentType::plaintext())
+            .set_payload(format!("{}\n1\n1\n", u64::MAX / 4))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_part_2() {
+        let app = test::init_service(App::new().configure(super::configure)).await;
+
+        let body = "5
+0 1 0
+-2 2 3
+3 -3 -5
+1 1 5
+4 3 5
+4
+0 1
+2 4
+3 4
+1 2
+";
+
+        let req = test::TestRequest::post()
+            .uri("/22/rocket")
+            .insert_header(ContentType::plaintext())
+            .set_payload(body)
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+
+        let body = resp.into_body();
+        let bytes = body::to_bytes(body).await.unwrap();
+        assert_eq!(bytes, "3 26.123");
+    }
+
+    #[actix_web::test]
+    async fn test_part_2_no_route() {
+        let app = test::init_service(App::new().configure(super::configure)).await;
+
+        let body = "2\n0 0 0\n1 1 1\n0\n";
+
+        let req = test::TestRequest::post()
+            .uri("/22/rocket")
+            .insert_header(ContentType::plaintext())
+            .set_payload(body)
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
 }
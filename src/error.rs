@@ -0,0 +1,219 @@
+use std::fmt;
+
+use actix_web::http::{header, StatusCode};
+use actix_web::{HttpResponse, ResponseError};
+use serde_json::json;
+
+/// Seconds clients are told to wait before retrying when a dependency is unavailable.
+pub const RETRY_AFTER_SECONDS: u64 = 5;
+
+#[derive(Debug)]
+pub enum AppError {
+    BadRequest(String),
+    /// The client may not act on the resource, e.g. a message sent by another user.
+    Forbidden(String),
+    NotFound(String),
+    Conflict(String),
+    /// The client sends faster than it is allowed to.
+    TooManyRequests(String),
+    Upstream(String),
+    /// The database cannot be reached; the request may succeed when retried later.
+    Unavailable(String),
+    Database(sqlx::Error),
+    Internal(String),
+}
+
+impl AppError {
+    pub fn bad_request(err: impl fmt::Display) -> Self {
+        AppError::BadRequest(err.to_string())
+    }
+
+    pub fn not_found(err: impl fmt::Display) -> Self {
+        AppError::NotFound(err.to_string())
+    }
+
+    pub fn upstream(err: impl fmt::Display) -> Self {
+        AppError::Upstream(err.to_string())
+    }
+
+    pub fn internal(err: impl fmt::Display) -> Self {
+        AppError::Internal(err.to_string())
+    }
+
+    /// The message shown to clients. Database errors may reveal queries, tables or constraints,
+    /// so they are logged and replaced by a generic message.
+    pub fn client_message(&self) -> String {
+        match self {
+            AppError::Database(err) => {
+                tracing::error!("database error: {}", err);
+                "database error".to_string()
+            }
+            err => err.to_string(),
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            AppError::BadRequest(_) => "bad_request",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::NotFound(_) => "not_found",
+            AppError::Conflict(_) => "conflict",
+            AppError::TooManyRequests(_) => "too_many_requests",
+            AppError::Upstream(_) => "upstream",
+            AppError::Unavailable(_) => "unavailable",
+            AppError::Database(_) => "database",
+            AppError::Internal(_) => "internal",
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::BadRequest(msg)
+            | AppError::Forbidden(msg)
+            | AppError::NotFound(msg)
+            | AppError::Conflict(msg)
+            | AppError::TooManyRequests(msg)
+            | AppError::Upstream(msg)
+            | AppError::Unavailable(msg)
+            | AppError::Internal(msg) => write!(f, "{}", msg),
+            AppError::Database(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Upstream(_) => StatusCode::BAD_GATEWAY,
+            AppError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Database(_) | AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut builder = HttpResponse::build(self.status_code());
+        if let AppError::Unavailable(_) = self {
+            builder.insert_header((header::RETRY_AFTER, RETRY_AFTER_SECONDS));
+        }
+        builder.json(json!({
+            "error": self.kind(),
+            "message": self.client_message(),
+        }))
+    }
+}
+
+/// Whether a SQLSTATE means the server cannot take queries right now rather than that the query
+/// is wrong: too many connections, shutting down or starting up, or a broken connection.
+fn is_unavailable_sqlstate(code: &str) -> bool {
+    matches!(code, "53300" | "57P01" | "57P02" | "57P03") || code.starts_with("08")
+}
+
+/// Whether the database could not be reached or could not take the query, so that it may
+/// succeed when retried later.
+pub fn is_unavailable(err: &sqlx::Error) -> bool {
+    match err {
+        sqlx::Error::PoolTimedOut
+        | sqlx::Error::PoolClosed
+        | sqlx::Error::WorkerCrashed
+        | sqlx::Error::Io(_)
+        | sqlx::Error::Tls(_) => true,
+        sqlx::Error::Database(db_err) => db_err.code().is_some_and(|code| is_unavailable_sqlstate(&code)),
+        _ => false,
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(err: sqlx::Error) -> Self {
+        match err {
+            sqlx::Error::RowNotFound => AppError::NotFound("no matching row".to_string()),
+            sqlx::Error::Database(_) if is_unavailable(&err) => {
+                // the server's message is no more use to clients than that of other database errors
+                tracing::warn!("database unavailable: {}", err);
+                AppError::Unavailable("database unavailable".to_string())
+            }
+            err if is_unavailable(&err) => AppError::Unavailable(format!("database unavailable: {}", err)),
+            err => AppError::Database(err),
+        }
+    }
+}
+
+impl From<reqwest::Error> for AppError {
+    fn from(err: reqwest::Error) -> Self {
+        AppError::Upstream(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{body, ResponseError};
+    use actix_web::http::StatusCode;
+    use sqlx::PgPool;
+
+    use super::AppError;
+
+    #[actix_web::test]
+    async fn test_error_response() {
+        let resp = AppError::bad_request("invalid number").error_response();
+
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+        let response: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+
+        assert_eq!(response["error"], "bad_request");
+        assert_eq!(response["message"], "invalid number");
+    }
+
+    #[test]
+    fn test_status_codes() {
+        assert_eq!(AppError::Forbidden("x".to_string()).status_code(), StatusCode::FORBIDDEN);
+        assert_eq!(AppError::not_found("x").status_code(), StatusCode::NOT_FOUND);
+        assert_eq!(AppError::Conflict("x".to_string()).status_code(), StatusCode::CONFLICT);
+        assert_eq!(AppError::TooManyRequests("x".to_string()).status_code(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(AppError::upstream("x").status_code(), StatusCode::BAD_GATEWAY);
+        assert_eq!(AppError::from(sqlx::Error::PoolTimedOut).status_code(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(AppError::from(sqlx::Error::ColumnNotFound("x".to_string())).status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[actix_web::test]
+    async fn test_database_details_hidden() {
+        let resp = AppError::from(sqlx::Error::ColumnNotFound("secret_column".to_string())).error_response();
+
+        let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+        let response: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+
+        assert_eq!(response["error"], "database");
+        assert_eq!(response["message"], "database error");
+    }
+
+    #[test]
+    fn test_retry_hint() {
+        let resp = AppError::from(sqlx::Error::PoolClosed).error_response();
+
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(resp.headers().get("retry-after").unwrap(), "5");
+    }
+
+    async fn raise(pool: &PgPool, code: &str) -> AppError {
+        let statement = format!("DO $$ BEGIN RAISE EXCEPTION 'raised' USING ERRCODE = '{}'; END $$", code);
+        sqlx::query(&statement).execute(pool).await.unwrap_err().into()
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_unavailable_sqlstates(pool: PgPool) {
+        for code in ["53300", "57P01", "57P03", "08006"] {
+            let err = raise(&pool, code).await;
+            assert!(matches!(err, AppError::Unavailable(_)), "{} should be unavailable", code);
+            assert_eq!(err.error_response().headers().get("retry-after").unwrap(), "5");
+        }
+
+        assert!(matches!(raise(&pool, "23505").await, AppError::Database(_)));
+    }
+}
@@ -1,8 +1,25 @@
+use std::sync::Arc;
+
 use actix_web::web::{Data, ServiceConfig};
 use shuttle_actix_web::ShuttleActixWeb;
 use sqlx::PgPool;
 
+use days::eight::pokemon::{self, PokemonSource};
+use days::nineteen::history::{self, ChatHistory};
+use days::nineteen::limits::ChatLimits;
+use days::nineteen::AppData as ChatData;
+use days::twentyone::country::{self, CountryResolver};
+use days::twelve::store::{PacketStore, PgPacketStore};
+use migrations::MigrationStatus;
+use orders::{OrderRepository, PgOrderRepository};
+
 mod days;
+mod error;
+mod health;
+mod migrations;
+mod orders;
+mod pagination;
+mod transfer;
 
 #[shuttle_runtime::main]
 async fn main(
@@ -14,9 +31,33 @@ async fn main(
     std::env::set_var("RUST_LOG", "info");
     std::env::set_var("RUST_BACKTRACE", "full");
 
+    // The database may still be coming up; `/ready` and the routes that need the database report
+    // unavailable until this is done.
+    let migration_status = Arc::new(MigrationStatus::default());
+    tokio::spawn(migrations::run(pool.clone(), migration_status.clone()));
+
+    let packet_store: Arc<dyn PacketStore> = Arc::new(PgPacketStore::new(pool.clone()));
+    let order_repository: Arc<dyn OrderRepository> = Arc::new(PgOrderRepository::new(pool.clone()));
+    let chat_history: Arc<dyn ChatHistory> =
+        history::from_env(pool.clone()).map_err(|err| shuttle_runtime::CustomError::msg(err.to_string()))?;
+    let chat_limits = ChatLimits::from_env().map_err(|err| shuttle_runtime::CustomError::msg(err.to_string()))?;
+    let chat_data = Arc::new(ChatData::new(chat_limits));
+    let pokemon_source: Arc<dyn PokemonSource> =
+        pokemon::from_env().map_err(|err| shuttle_runtime::CustomError::msg(err.to_string()))?;
+    let country_resolver: Arc<dyn CountryResolver> =
+        country::from_env().map_err(|err| shuttle_runtime::CustomError::msg(err.to_string()))?;
+
     let config = move |cfg: &mut ServiceConfig| {
+        cfg.configure(health::configure);
         cfg.configure(days::configure);
         cfg.app_data(Data::new(pool.clone()));
+        cfg.app_data(Data::from(migration_status.clone()));
+        cfg.app_data(Data::from(packet_store.clone()));
+        cfg.app_data(Data::from(order_repository.clone()));
+        cfg.app_data(Data::from(chat_history.clone()));
+        cfg.app_data(Data::from(chat_data.clone()));
+        cfg.app_data(Data::from(pokemon_source.clone()));
+        cfg.app_data(Data::from(country_resolver.clone()));
     };
 
     Ok(config.into())
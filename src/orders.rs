@@ -0,0 +1,1782 @@
+use std::collections::{HashMap, HashSet};
+#[cfg(test)]
+use std::cmp::Ordering;
+#[cfg(test)]
+use std::collections::BTreeMap;
+
+use actix_web::HttpResponse;
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use futures::TryStreamExt;
+use serde::{Deserialize, Deserializer, Serialize};
+use sqlx::{PgPool, Postgres, QueryBuilder};
+#[cfg(test)]
+use tokio::sync::Mutex;
+
+use crate::error::AppError;
+
+// `orders.gift_name` and `regions.name` are VARCHAR(50)s
+const MAX_GIFT_NAME_LENGTH: usize = 50;
+const MAX_REGION_NAME_LENGTH: usize = 50;
+
+#[derive(Serialize, Deserialize, Clone, sqlx::FromRow)]
+pub struct Order {
+    pub id: i32,
+    pub region_id: i32,
+    /// Required for new orders, but the column is nullable, so rows written before may lack it.
+    pub gift_name: Option<String>,
+    pub quantity: i32,
+}
+
+#[derive(Serialize, Deserialize, Clone, sqlx::FromRow)]
+pub struct Region {
+    pub id: i32,
+    /// `None` for the placeholder regions created for day 13 orders.
+    pub name: Option<String>,
+    /// The enclosing region, `None` for top-level regions.
+    #[serde(default)]
+    pub parent_id: Option<i32>,
+}
+
+#[derive(Serialize)]
+pub struct RegionTotal {
+    pub region: String,
+    pub total: i64,
+}
+
+/// A region with the total quantity ordered in it and all of its descendants.
+pub struct RegionRollup {
+    pub id: i32,
+    pub name: Option<String>,
+    pub parent_id: Option<i32>,
+    pub total: i64,
+}
+
+#[derive(Serialize)]
+pub struct GiftTotal {
+    pub gift_name: String,
+    pub quantity: i64,
+}
+
+/// The most popular gifts of a region, as names or as [`GiftTotal`]s.
+#[derive(Serialize)]
+pub struct RegionTopList<T = String> {
+    pub region: String,
+    pub top_gifts: Vec<T>,
+}
+
+/// Which gifts make a region's top list. Gifts are ranked by total quantity, ties broken by
+/// name; with `ties` every gift tied with the last one kept is kept too.
+pub struct TopListQuery {
+    pub limit: i64,
+    /// Whether the orders of a region's descendants count towards its gifts.
+    pub rollup: bool,
+    pub ties: bool,
+    /// Leaves out gifts with a lower total quantity in the region.
+    pub min_quantity: Option<i64>,
+}
+
+/// Changes to an order; fields left as `None` are kept.
+#[derive(Deserialize)]
+pub struct OrderPatch {
+    pub region_id: Option<i32>,
+    pub gift_name: Option<String>,
+    pub quantity: Option<i32>,
+}
+
+/// Changes to a region; fields left out are kept.
+#[derive(Deserialize)]
+pub struct RegionPatch {
+    pub name: Option<String>,
+    /// The new parent, `Some(None)` to make the region a top-level one.
+    #[serde(default, deserialize_with = "present")]
+    pub parent_id: Option<Option<i32>>,
+}
+
+/// Deserializes a field that is present, even as `null`, into `Some`.
+fn present<'de, D: Deserializer<'de>, T: Deserialize<'de>>(deserializer: D) -> Result<Option<T>, D::Error> {
+    T::deserialize(deserializer).map(Some)
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    #[default]
+    Asc,
+    Desc,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderSort {
+    #[default]
+    Id,
+    Quantity,
+    GiftName,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RegionSort {
+    #[default]
+    Id,
+    Name,
+}
+
+/// A page of orders in `sort` order with the id as tie-breaker, starting after the `after`
+/// order; orders without a gift name sort as if named with the empty string. Filters left as
+/// `None` match every order.
+pub struct OrderListQuery {
+    pub sort: OrderSort,
+    pub direction: Direction,
+    pub after: Option<Order>,
+    pub limit: i64,
+    pub region_id: Option<i32>,
+    pub gift_prefix: Option<String>,
+    pub min_quantity: Option<i32>,
+    pub max_quantity: Option<i32>,
+}
+
+/// A page of regions in `sort` order with the id as tie-breaker; nameless regions sort as if
+/// named with the empty string.
+pub struct RegionListQuery {
+    pub sort: RegionSort,
+    pub direction: Direction,
+    pub after: Option<Region>,
+    pub limit: i64,
+    pub name_prefix: Option<String>,
+}
+
+/// Which orders to aggregate and how; filters left as `None` match every order.
+pub struct AnalyticsQuery {
+    pub by_gift: bool,
+    pub by_region: bool,
+    pub region_ids: Option<Vec<i32>>,
+    pub gift_prefix: Option<String>,
+    pub min_quantity: Option<i32>,
+    pub top: i64,
+}
+
+/// The total quantity of one group; the keys that were not grouped by are `None`.
+pub struct GroupTotal {
+    pub gift_name: Option<String>,
+    pub region_id: Option<i32>,
+    pub region: Option<String>,
+    pub quantity: i64,
+}
+
+pub struct OrderAnalytics {
+    /// Total quantity of all orders matching the filters, including groups beyond the top.
+    pub total: i64,
+    pub groups: Vec<GroupTotal>,
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OnConflict {
+    /// Reject the whole batch when any order cannot be inserted.
+    #[default]
+    Fail,
+    /// Leave existing orders untouched and insert the rest.
+    Skip,
+    /// Overwrite existing orders with the same id.
+    Upsert,
+}
+
+#[derive(Deserialize)]
+pub struct InsertParams {
+    #[serde(default)]
+    pub on_conflict: OnConflict,
+}
+
+/// What to do with orders referring to a region that does not exist.
+#[derive(Clone, Copy, PartialEq)]
+pub enum MissingRegions {
+    /// Create a nameless placeholder region, for days that have no notion of regions.
+    Create,
+    Reject,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum RowStatus {
+    Skipped,
+    Rejected,
+}
+
+#[derive(Serialize)]
+struct RowOutcome {
+    /// Position of the order in the submitted batch.
+    index: usize,
+    id: i32,
+    status: RowStatus,
+    reason: String,
+}
+
+/// Summary of a bulk insert; `rows` only lists the orders that were not written.
+#[derive(Serialize, Default)]
+pub struct InsertReport {
+    committed: bool,
+    inserted: usize,
+    updated: usize,
+    skipped: usize,
+    rejected: usize,
+    rows: Vec<RowOutcome>,
+}
+
+impl InsertReport {
+    fn skip(&mut self, index: usize, id: i32, reason: String) {
+        self.skipped += 1;
+        self.rows.push(RowOutcome { index, id, status: RowStatus::Skipped, reason });
+    }
+
+    fn reject(&mut self, index: usize, id: i32, reason: String) {
+        self.rejected += 1;
+        self.rows.push(RowOutcome { index, id, status: RowStatus::Rejected, reason });
+    }
+
+    /// Records an order whose id already exists, which is only skipped with `on_conflict=skip`.
+    fn conflict(&mut self, index: usize, id: i32, on_conflict: OnConflict) {
+        let reason = format!("order id {} already exists", id);
+        match on_conflict {
+            OnConflict::Skip => self.skip(index, id, reason),
+            _ => self.reject(index, id, reason),
+        }
+    }
+
+    fn must_roll_back(&self, on_conflict: OnConflict) -> bool {
+        on_conflict == OnConflict::Fail && self.rejected > 0
+    }
+
+    /// `200 OK` when the batch was committed, `409 Conflict` when it was rolled back.
+    pub fn into_response(mut self) -> HttpResponse {
+        self.rows.sort_by_key(|row| row.index);
+        if self.committed {
+            HttpResponse::Ok().json(self)
+        } else {
+            HttpResponse::Conflict().json(self)
+        }
+    }
+}
+
+/// Rejects orders that are invalid or duplicated within the batch, and with `known_regions`
+/// those referring to an unknown region. Returns the indices of the remaining orders.
+fn validate(orders: &[Order], known_regions: Option<&HashSet<i32>>, report: &mut InsertReport) -> Vec<usize> {
+    let mut seen = HashSet::new();
+    let mut accepted = Vec::with_capacity(orders.len());
+    for (index, order) in orders.iter().enumerate() {
+        let reason = if !seen.insert(order.id) {
+            Some(format!("duplicate order id {} in batch", order.id))
+        } else if order.gift_name.is_none() {
+            Some(format!("order {} has no gift name", order.id))
+        } else if let Err(err) = order.gift_name.as_deref().map_or(Ok(()), check_gift_name) {
+            Some(err.to_string())
+        } else if known_regions.is_some_and(|known| !known.contains(&order.region_id)) {
+            Some(format!("unknown region {}", order.region_id))
+        } else {
+            None
+        };
+
+        match reason {
+            Some(reason) => report.reject(index, order.id, reason),
+            None => accepted.push(index),
+        }
+    }
+    accepted
+}
+
+fn check_gift_name(gift_name: &str) -> Result<(), AppError> {
+    if gift_name.chars().count() > MAX_GIFT_NAME_LENGTH {
+        return Err(AppError::BadRequest(format!("gift name longer than {} characters", MAX_GIFT_NAME_LENGTH)));
+    }
+    Ok(())
+}
+
+fn check_region_name(name: Option<&str>) -> Result<(), AppError> {
+    if name.is_some_and(|name| name.chars().count() > MAX_REGION_NAME_LENGTH) {
+        return Err(AppError::BadRequest(format!("region name longer than {} characters", MAX_REGION_NAME_LENGTH)));
+    }
+    Ok(())
+}
+
+/// Fails if any region of the batch has no name or an overly long one.
+fn check_region_names(regions: &[Region]) -> Result<(), AppError> {
+    regions.iter().try_for_each(|region| match &region.name {
+        Some(name) => check_region_name(Some(name)),
+        None => Err(AppError::BadRequest(format!("region {} has no name", region.id))),
+    })
+}
+
+fn unknown_region(region_id: i32) -> AppError {
+    AppError::Conflict(format!("unknown region {}", region_id))
+}
+
+fn region_in_use(region_id: i32) -> AppError {
+    AppError::Conflict(format!("region {} still has orders", region_id))
+}
+
+fn region_has_subregions(region_id: i32) -> AppError {
+    AppError::Conflict(format!("region {} still has subregions", region_id))
+}
+
+fn unknown_parent_region(parent_id: i32) -> AppError {
+    AppError::Conflict(format!("unknown parent region {}", parent_id))
+}
+
+fn region_cycle(region_id: i32) -> AppError {
+    AppError::Conflict(format!("region {} cannot be its own ancestor", region_id))
+}
+
+/// Turns a foreign key violation into the given conflict.
+fn map_foreign_key_violation(err: sqlx::Error, conflict: AppError) -> AppError {
+    match err.as_database_error() {
+        Some(db_err) if db_err.is_foreign_key_violation() => conflict,
+        _ => err.into(),
+    }
+}
+
+/// Fails if a region id appears twice in the batch or is already taken, or if a parent is
+/// neither in `existing` nor in the batch. `existing` must hold at least the existing regions
+/// among the batch's ids and parent ids.
+fn check_region_ids(regions: &[Region], existing: &HashSet<i32>) -> Result<(), AppError> {
+    let mut seen = HashSet::new();
+    if let Some(region) = regions.iter().find(|region| !seen.insert(region.id) || existing.contains(&region.id)) {
+        return Err(AppError::BadRequest(format!("region {} already exists", region.id)));
+    }
+
+    let parents: HashMap<i32, i32> = regions
+        .iter()
+        .filter_map(|region| region.parent_id.map(|parent_id| (region.id, parent_id)))
+        .collect();
+    for region in regions {
+        // Existing regions only have existing ancestors, so a cycle can only go through the batch.
+        let mut current = region.id;
+        for _ in 0..=parents.len() {
+            let Some(&parent_id) = parents.get(&current) else {
+                break;
+            };
+            if parent_id == region.id {
+                return Err(AppError::BadRequest(format!("region {} is its own ancestor", region.id)));
+            }
+            if !seen.contains(&parent_id) && !existing.contains(&parent_id) {
+                return Err(AppError::BadRequest(format!("unknown parent region {}", parent_id)));
+            }
+            current = parent_id;
+        }
+    }
+    Ok(())
+}
+
+/// Rows read from the database as the stream is polled, so that exports do not hold whole tables
+/// in memory.
+pub type RowStream<T> = BoxStream<'static, Result<T, AppError>>;
+
+/// Storage for the orders and regions of days 13 and 18.
+#[async_trait]
+pub trait OrderRepository: Send + Sync {
+    /// Removes all orders and regions.
+    async fn reset(&self) -> Result<(), AppError>;
+    /// Inserts a batch of orders atomically. Orders that are invalid or duplicated within the
+    /// batch are rejected; orders whose id already exists are handled according to `on_conflict`.
+    async fn insert_orders(
+        &self,
+        orders: &[Order],
+        on_conflict: OnConflict,
+        missing_regions: MissingRegions,
+    ) -> Result<InsertReport, AppError>;
+    /// Inserts a batch of regions atomically, failing if any id is already taken or any region
+    /// has no name, as only placeholders go without one.
+    async fn insert_regions(&self, regions: &[Region]) -> Result<(), AppError>;
+    async fn total_quantity(&self) -> Result<i64, AppError>;
+    /// The gift with the highest total quantity, ties broken by name.
+    async fn most_popular_gift(&self) -> Result<Option<String>, AppError>;
+    /// Total quantity per region name, for regions with orders, sorted by name. With `rollup`
+    /// totals are per region instead, include the orders of its descendants and are ties broken
+    /// by id, as names may repeat across levels.
+    ///
+    /// Like the other region reports, leaves out the placeholder regions of day 13 orders.
+    async fn region_totals(&self, rollup: bool) -> Result<Vec<RegionTotal>, AppError>;
+    /// The top list of every region, including those without orders, sorted by region name and
+    /// then id.
+    async fn top_gifts(&self, query: &TopListQuery) -> Result<Vec<RegionTopList<GiftTotal>>, AppError>;
+    /// Every region with its rolled-up total, sorted by id.
+    async fn region_rollups(&self) -> Result<Vec<RegionRollup>, AppError>;
+    /// The `query.top` groups with the highest total quantity, ties broken by gift name and then
+    /// region id.
+    async fn analytics(&self, query: &AnalyticsQuery) -> Result<OrderAnalytics, AppError>;
+    /// All orders, sorted by id.
+    async fn all_orders(&self) -> Result<RowStream<Order>, AppError>;
+    /// All regions, sorted by id.
+    async fn all_regions(&self) -> Result<RowStream<Region>, AppError>;
+    async fn get_order(&self, id: i32) -> Result<Option<Order>, AppError>;
+    /// Applies the patch, returning the updated order or `None` when there is no such order.
+    async fn update_order(&self, id: i32, patch: &OrderPatch) -> Result<Option<Order>, AppError>;
+    async fn delete_order(&self, id: i32) -> Result<bool, AppError>;
+    /// Up to `query.limit + 1` orders, the extra one telling whether there is another page.
+    async fn list_orders(&self, query: &OrderListQuery) -> Result<Vec<Order>, AppError>;
+    async fn get_region(&self, id: i32) -> Result<Option<Region>, AppError>;
+    /// Applies the patch, returning the updated region or `None` when there is no such region.
+    /// Fails with a conflict when the new parent is unknown or would make the region its own
+    /// ancestor.
+    async fn update_region(&self, id: i32, patch: &RegionPatch) -> Result<Option<Region>, AppError>;
+    /// Deletes the region, failing with a conflict while orders or subregions still refer to it.
+    async fn delete_region(&self, id: i32) -> Result<bool, AppError>;
+    /// Up to `query.limit + 1` regions, the extra one telling whether there is another page.
+    async fn list_regions(&self, query: &RegionListQuery) -> Result<Vec<Region>, AppError>;
+}
+
+fn push_direction(builder: &mut QueryBuilder<'_, Postgres>, direction: Direction) {
+    builder.push(match direction {
+        Direction::Asc => " ASC",
+        Direction::Desc => " DESC",
+    });
+}
+
+fn push_keyset_operator(builder: &mut QueryBuilder<'_, Postgres>, direction: Direction) {
+    builder.push(match direction {
+        Direction::Asc => " > ",
+        Direction::Desc => " < ",
+    });
+}
+
+pub struct PgOrderRepository {
+    pool: PgPool,
+}
+
+impl PgOrderRepository {
+    pub fn new(pool: PgPool) -> Self {
+        PgOrderRepository { pool }
+    }
+}
+
+#[async_trait]
+impl OrderRepository for PgOrderRepository {
+    async fn reset(&self) -> Result<(), AppError> {
+        sqlx::query!("TRUNCATE orders, regions")
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn insert_orders(
+        &self,
+        orders: &[Order],
+        on_conflict: OnConflict,
+        missing_regions: MissingRegions,
+    ) -> Result<InsertReport, AppError> {
+        let mut report = InsertReport::default();
+
+        let known_regions: Option<HashSet<i32>> = match missing_regions {
+            MissingRegions::Create => None,
+            MissingRegions::Reject => {
+                let region_ids: Vec<i32> = orders.iter().map(|order| order.region_id).collect();
+                let known = sqlx::query!("SELECT id FROM regions WHERE id = ANY($1)", &region_ids)
+                    .fetch_all(&self.pool)
+                    .await?
+                    .into_iter()
+                    .map(|row| row.id)
+                    .collect();
+                Some(known)
+            }
+        };
+
+        let accepted = validate(orders, known_regions.as_ref(), &mut report);
+        if report.must_roll_back(on_conflict) {
+            return Ok(report);
+        }
+
+        let ids: Vec<i32> = accepted.iter().map(|&index| orders[index].id).collect();
+        let region_ids: Vec<i32> = accepted.iter().map(|&index| orders[index].region_id).collect();
+        let gift_names: Vec<Option<String>> = accepted.iter().map(|&index| orders[index].gift_name.clone()).collect();
+        let quantities: Vec<i32> = accepted.iter().map(|&index| orders[index].quantity).collect();
+
+        let mut tx = self.pool.begin().await?;
+
+        if missing_regions == MissingRegions::Create {
+            sqlx::query!(
+                "INSERT INTO regions (id) SELECT DISTINCT UNNEST($1::int[]) ON CONFLICT DO NOTHING",
+                &region_ids
+            )
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        let written: HashSet<i32> = match on_conflict {
+            OnConflict::Fail | OnConflict::Skip => {
+                let inserted: HashSet<i32> = sqlx::query!(
+                    "INSERT INTO orders (id, region_id, gift_name, quantity)
+                    SELECT * FROM UNNEST($1::int[], $2::int[], $3::text[], $4::int[])
+                    ON CONFLICT (id) DO NOTHING
+                    RETURNING id",
+                    &ids,
+                    &region_ids,
+                    &gift_names as &[Option<String>],
+                    &quantities
+                )
+                    .fetch_all(&mut *tx)
+                    .await?
+                    .into_iter()
+                    .map(|row| row.id)
+                    .collect();
+                report.inserted = inserted.len();
+                inserted
+            }
+            OnConflict::Upsert => {
+                let rows = sqlx::query!(
+                    "INSERT INTO orders (id, region_id, gift_name, quantity)
+                    SELECT * FROM UNNEST($1::int[], $2::int[], $3::text[], $4::int[])
+                    ON CONFLICT (id) DO UPDATE SET
+                        region_id = EXCLUDED.region_id,
+                        gift_name = EXCLUDED.gift_name,
+                        quantity = EXCLUDED.quantity
+                    RETURNING id, (xmax = 0) \"inserted!\"",
+                    &ids,
+                    &region_ids,
+                    &gift_names as &[Option<String>],
+                    &quantities
+                )
+                    .fetch_all(&mut *tx)
+                    .await?;
+                report.inserted = rows.iter().filter(|row| row.inserted).count();
+                report.updated = rows.len() - report.inserted;
+                rows.into_iter().map(|row| row.id).collect()
+            }
+        };
+
+        for &index in &accepted {
+            if !written.contains(&orders[index].id) {
+                report.conflict(index, orders[index].id, on_conflict);
+            }
+        }
+
+        if report.must_roll_back(on_conflict) {
+            tx.rollback().await?;
+            report.inserted = 0;
+            return Ok(report);
+        }
+
+        tx.commit().await?;
+        report.committed = true;
+        Ok(report)
+    }
+
+    async fn insert_regions(&self, regions: &[Region]) -> Result<(), AppError> {
+        check_region_names(regions)?;
+
+        let ids: Vec<i32> = regions.iter().map(|region| region.id).collect();
+        let names: Vec<Option<String>> = regions.iter().map(|region| region.name.clone()).collect();
+        let parent_ids: Vec<Option<i32>> = regions.iter().map(|region| region.parent_id).collect();
+
+        let existing = sqlx::query!(
+            "SELECT id FROM regions WHERE id = ANY($1) OR id = ANY($2)",
+            &ids,
+            &parent_ids as &[Option<i32>]
+        )
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(|row| row.id)
+            .collect();
+        check_region_ids(regions, &existing)?;
+
+        // the foreign key is checked at the end of the statement, so parents may follow their children
+        sqlx::query!(
+            "INSERT INTO regions (id, name, parent_id) SELECT * FROM UNNEST($1::int[], $2::text[], $3::int[])",
+            &ids,
+            &names as &[Option<String>],
+            &parent_ids as &[Option<i32>]
+        )
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn total_quantity(&self) -> Result<i64, AppError> {
+        let total = sqlx::query!("SELECT SUM(quantity) total FROM orders")
+            .fetch_one(&self.pool)
+            .await?
+            .total
+            .unwrap_or_default();
+
+        Ok(total)
+    }
+
+    async fn most_popular_gift(&self) -> Result<Option<String>, AppError> {
+        let popular = sqlx::query!(
+            "SELECT gift_name FROM orders WHERE gift_name IS NOT NULL GROUP BY gift_name ORDER BY SUM(quantity) DESC, gift_name LIMIT 1"
+        )
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(popular.and_then(|row| row.gift_name))
+    }
+
+    async fn region_totals(&self, rollup: bool) -> Result<Vec<RegionTotal>, AppError> {
+        if rollup {
+            let totals = sqlx::query!(
+                "WITH RECURSIVE subtrees (root_id, region_id) AS (
+                    SELECT id, id FROM regions
+                    UNION
+                    SELECT subtrees.root_id, regions.id FROM subtrees JOIN regions ON regions.parent_id = subtrees.region_id
+                )
+                SELECT
+                    regions.name region,
+                    SUM(orders.quantity) \"total!\"
+                FROM
+                    subtrees
+                    JOIN orders ON orders.region_id = subtrees.region_id
+                    JOIN regions ON regions.id = subtrees.root_id
+                WHERE
+                    regions.name IS NOT NULL
+                GROUP BY
+                    regions.id
+                ORDER BY
+                    COALESCE(regions.name, ''), regions.id"
+            )
+                .fetch_all(&self.pool)
+                .await?
+                .into_iter()
+                .map(|row| RegionTotal {
+                    region: row.region.unwrap_or_default(),
+                    total: row.total,
+                })
+                .collect();
+
+            return Ok(totals);
+        }
+
+        let totals = sqlx::query!(
+            "SELECT COALESCE(regions.name, '') \"region!\", SUM(orders.quantity) total FROM orders JOIN regions ON orders.region_id = regions.id WHERE regions.name IS NOT NULL GROUP BY 1 ORDER BY 1"
+        )
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(|row| RegionTotal {
+                region: row.region,
+                total: row.total.unwrap_or_default(),
+            })
+            .collect();
+
+        Ok(totals)
+    }
+
+    async fn top_gifts(&self, query: &TopListQuery) -> Result<Vec<RegionTopList<GiftTotal>>, AppError> {
+        // Regions without (matching) orders get a single row without a gift.
+        let rows = sqlx::query!(
+            "WITH RECURSIVE subtrees (root_id, region_id) AS (
+                SELECT id, id FROM regions
+                UNION
+                SELECT subtrees.root_id, regions.id FROM subtrees JOIN regions ON regions.parent_id = subtrees.region_id WHERE $1
+            ),
+            gifts AS (
+                SELECT
+                    subtrees.root_id region_id,
+                    orders.gift_name,
+                    SUM(orders.quantity) quantity,
+                    RANK() OVER (PARTITION BY subtrees.root_id ORDER BY SUM(orders.quantity) DESC) rank,
+                    ROW_NUMBER() OVER (PARTITION BY subtrees.root_id ORDER BY SUM(orders.quantity) DESC, orders.gift_name) row_number
+                FROM
+                    subtrees
+                    JOIN orders ON orders.region_id = subtrees.region_id
+                WHERE
+                    orders.gift_name IS NOT NULL
+                GROUP BY
+                    subtrees.root_id, orders.gift_name
+                HAVING
+                    $2::bigint IS NULL OR SUM(orders.quantity) >= $2
+            )
+            SELECT
+                regions.id,
+                COALESCE(regions.name, '') \"region!\",
+                gifts.gift_name,
+                gifts.quantity
+            FROM
+                regions
+                LEFT JOIN gifts ON gifts.region_id = regions.id
+                    AND CASE WHEN $3 THEN gifts.rank ELSE gifts.row_number END <= $4
+            WHERE
+                regions.name IS NOT NULL
+            ORDER BY
+                2, 1, gifts.row_number",
+            query.rollup,
+            query.min_quantity,
+            query.ties,
+            query.limit
+        )
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut regions_top_list: Vec<RegionTopList<GiftTotal>> = Vec::new();
+        let mut last_id = None;
+        for row in rows {
+            if last_id != Some(row.id) {
+                last_id = Some(row.id);
+                regions_top_list.push(RegionTopList { region: row.region, top_gifts: Vec::new() });
+            }
+            if let (Some(gift_name), Some(quantity), Some(top_list)) = (row.gift_name, row.quantity, regions_top_list.last_mut()) {
+                top_list.top_gifts.push(GiftTotal { gift_name, quantity });
+            }
+        }
+
+        Ok(regions_top_list)
+    }
+
+    async fn region_rollups(&self) -> Result<Vec<RegionRollup>, AppError> {
+        let rollups = sqlx::query_as!(
+            RegionRollup,
+            "WITH RECURSIVE subtrees (root_id, region_id) AS (
+                SELECT id, id FROM regions
+                UNION
+                SELECT subtrees.root_id, regions.id FROM subtrees JOIN regions ON regions.parent_id = subtrees.region_id
+            )
+            SELECT
+                regions.id,
+                regions.name,
+                regions.parent_id,
+                COALESCE(SUM(orders.quantity), 0) \"total!\"
+            FROM
+                regions
+                JOIN subtrees ON subtrees.root_id = regions.id
+                LEFT JOIN orders ON orders.region_id = subtrees.region_id
+            WHERE
+                regions.name IS NOT NULL
+            GROUP BY
+                regions.id
+            ORDER BY
+                regions.id"
+        )
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rollups)
+    }
+
+    async fn analytics(&self, query: &AnalyticsQuery) -> Result<OrderAnalytics, AppError> {
+        let rows = sqlx::query!(
+            "SELECT
+                CASE WHEN $1 THEN orders.gift_name END gift_name,
+                CASE WHEN $2 THEN orders.region_id END region_id,
+                CASE WHEN $2 THEN regions.name END region,
+                SUM(orders.quantity) \"quantity!\",
+                SUM(SUM(orders.quantity)) OVER ()::BIGINT \"total!\"
+            FROM
+                orders
+                LEFT JOIN regions ON regions.id = orders.region_id
+            WHERE
+                ($3::int[] IS NULL OR orders.region_id = ANY($3))
+                AND ($4::text IS NULL OR starts_with(orders.gift_name, $4))
+                AND ($5::int IS NULL OR orders.quantity >= $5)
+            GROUP BY
+                1, 2, 3
+            ORDER BY
+                4 DESC, 1 NULLS FIRST, 2
+            LIMIT $6",
+            query.by_gift,
+            query.by_region,
+            query.region_ids.as_deref(),
+            query.gift_prefix,
+            query.min_quantity,
+            query.top
+        )
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(OrderAnalytics {
+            total: rows.first().map(|row| row.total).unwrap_or_default(),
+            groups: rows
+                .into_iter()
+                .map(|row| GroupTotal {
+                    gift_name: row.gift_name,
+                    region_id: row.region_id,
+                    region: row.region,
+                    quantity: row.quantity,
+                })
+                .collect(),
+        })
+    }
+
+    async fn all_orders(&self) -> Result<RowStream<Order>, AppError> {
+        let pool = self.pool.clone();
+        Ok(Box::pin(async_stream::try_stream! {
+            let mut orders = sqlx::query_as!(
+                Order,
+                "SELECT id, region_id, gift_name, quantity FROM orders ORDER BY id"
+            )
+                .fetch(&pool);
+
+            while let Some(order) = orders.try_next().await? {
+                yield order;
+            }
+        }))
+    }
+
+    async fn all_regions(&self) -> Result<RowStream<Region>, AppError> {
+        let pool = self.pool.clone();
+        Ok(Box::pin(async_stream::try_stream! {
+            let mut regions = sqlx::query_as!(Region, "SELECT id, name, parent_id FROM regions ORDER BY id").fetch(&pool);
+
+            while let Some(region) = regions.try_next().await? {
+                yield region;
+            }
+        }))
+    }
+
+    async fn get_order(&self, id: i32) -> Result<Option<Order>, AppError> {
+        let order = sqlx::query_as!(
+            Order,
+            "SELECT id, region_id, gift_name, quantity FROM orders WHERE id = $1",
+            id
+        )
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(order)
+    }
+
+    async fn update_order(&self, id: i32, patch: &OrderPatch) -> Result<Option<Order>, AppError> {
+        if let Some(gift_name) = &patch.gift_name {
+            check_gift_name(gift_name)?;
+        }
+
+        let order = sqlx::query_as!(
+            Order,
+            "UPDATE orders SET
+                region_id = COALESCE($2, region_id),
+                gift_name = COALESCE($3, gift_name),
+                quantity = COALESCE($4, quantity)
+            WHERE id = $1
+            RETURNING id, region_id, gift_name, quantity",
+            id,
+            patch.region_id,
+            patch.gift_name,
+            patch.quantity
+        )
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|err| map_foreign_key_violation(err, unknown_region(patch.region_id.unwrap_or_default())))?;
+
+        Ok(order)
+    }
+
+    async fn delete_order(&self, id: i32) -> Result<bool, AppError> {
+        let result = sqlx::query!("DELETE FROM orders WHERE id = $1", id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn list_orders(&self, query: &OrderListQuery) -> Result<Vec<Order>, AppError> {
+        let mut builder = QueryBuilder::new("SELECT id, region_id, gift_name, quantity FROM orders WHERE TRUE");
+
+        if let Some(region_id) = query.region_id {
+            builder.push(" AND region_id = ").push_bind(region_id);
+        }
+        if let Some(gift_prefix) = &query.gift_prefix {
+            builder.push(" AND starts_with(gift_name, ").push_bind(gift_prefix.clone()).push(")");
+        }
+        if let Some(min_quantity) = query.min_quantity {
+            builder.push(" AND quantity >= ").push_bind(min_quantity);
+        }
+        if let Some(max_quantity) = query.max_quantity {
+            builder.push(" AND quantity <= ").push_bind(max_quantity);
+        }
+
+        let column = match query.sort {
+            OrderSort::Id => None,
+            OrderSort::Quantity => Some("quantity"),
+            OrderSort::GiftName => Some("COALESCE(gift_name, '')"),
+        };
+
+        if let Some(after) = &query.after {
+            match query.sort {
+                OrderSort::Id => {
+                    builder.push(" AND id");
+                    push_keyset_operator(&mut builder, query.direction);
+                    builder.push_bind(after.id);
+                }
+                OrderSort::Quantity => {
+                    builder.push(" AND (quantity, id)");
+                    push_keyset_operator(&mut builder, query.direction);
+                    builder.push("(").push_bind(after.quantity).push(", ").push_bind(after.id).push(")");
+                }
+                OrderSort::GiftName => {
+                    builder.push(" AND (COALESCE(gift_name, ''), id)");
+                    push_keyset_operator(&mut builder, query.direction);
+                    builder
+                        .push("(")
+                        .push_bind(after.gift_name.clone().unwrap_or_default())
+                        .push(", ")
+                        .push_bind(after.id)
+                        .push(")");
+                }
+            }
+        }
+
+        builder.push(" ORDER BY ");
+        if let Some(column) = column {
+            builder.push(column);
+            push_direction(&mut builder, query.direction);
+            builder.push(", ");
+        }
+        builder.push("id");
+        push_direction(&mut builder, query.direction);
+        builder.push(" LIMIT ").push_bind(query.limit + 1);
+
+        let orders = builder
+            .build_query_as::<Order>()
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(orders)
+    }
+
+    async fn get_region(&self, id: i32) -> Result<Option<Region>, AppError> {
+        let region = sqlx::query_as!(Region, "SELECT id, name, parent_id FROM regions WHERE id = $1", id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(region)
+    }
+
+    async fn update_region(&self, id: i32, patch: &RegionPatch) -> Result<Option<Region>, AppError> {
+        check_region_name(patch.name.as_deref())?;
+
+        let mut tx = self.pool.begin().await?;
+        if let Some(Some(parent_id)) = patch.parent_id {
+            // Parents are changed one at a time, so that two changes cannot close a cycle together.
+            sqlx::query!("LOCK TABLE regions IN SHARE ROW EXCLUSIVE MODE").execute(&mut *tx).await?;
+            let cycle = sqlx::query_scalar!(
+                "WITH RECURSIVE ancestors (id) AS (
+                    SELECT $2::int
+                    UNION
+                    SELECT regions.parent_id FROM ancestors JOIN regions ON regions.id = ancestors.id
+                    WHERE regions.parent_id IS NOT NULL
+                )
+                SELECT EXISTS (SELECT FROM ancestors WHERE id = $1) \"cycle!\"",
+                id,
+                parent_id
+            )
+                .fetch_one(&mut *tx)
+                .await?;
+            if cycle {
+                return Err(region_cycle(id));
+            }
+        }
+
+        let parent_id = patch.parent_id.flatten();
+        let region = sqlx::query_as!(
+            Region,
+            "UPDATE regions SET
+                name = COALESCE($2, name),
+                parent_id = CASE WHEN $3 THEN $4 ELSE parent_id END
+            WHERE id = $1
+            RETURNING id, name, parent_id",
+            id,
+            patch.name,
+            patch.parent_id.is_some(),
+            parent_id
+        )
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|err| map_foreign_key_violation(err, unknown_parent_region(parent_id.unwrap_or_default())))?;
+        tx.commit().await?;
+
+        Ok(region)
+    }
+
+    async fn delete_region(&self, id: i32) -> Result<bool, AppError> {
+        let result = sqlx::query!("DELETE FROM regions WHERE id = $1", id)
+            .execute(&self.pool)
+            .await
+            .map_err(|err| {
+                let conflict = match err.as_database_error().and_then(|db_err| db_err.constraint()) {
+                    Some("regions_parent_id_fkey") => region_has_subregions(id),
+                    _ => region_in_use(id),
+                };
+                map_foreign_key_violation(err, conflict)
+            })?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn list_regions(&self, query: &RegionListQuery) -> Result<Vec<Region>, AppError> {
+        let mut builder = QueryBuilder::new("SELECT id, name, parent_id FROM regions WHERE TRUE");
+
+        if let Some(name_prefix) = &query.name_prefix {
+            builder.push(" AND starts_with(name, ").push_bind(name_prefix.clone()).push(")");
+        }
+
+        if let Some(after) = &query.after {
+            match query.sort {
+                RegionSort::Id => {
+                    builder.push(" AND id");
+                    push_keyset_operator(&mut builder, query.direction);
+                    builder.push_bind(after.id);
+                }
+                RegionSort::Name => {
+                    builder.push(" AND (COALESCE(name, ''), id)");
+                    push_keyset_operator(&mut builder, query.direction);
+                    builder
+                        .push("(")
+                        .push_bind(after.name.clone().unwrap_or_default())
+                        .push(", ")
+                        .push_bind(after.id)
+                        .push(")");
+                }
+            }
+        }
+
+        builder.push(" ORDER BY ");
+        if let RegionSort::Name = query.sort {
+            builder.push("COALESCE(name, '')");
+            push_direction(&mut builder, query.direction);
+            builder.push(", ");
+        }
+        builder.push("id");
+        push_direction(&mut builder, query.direction);
+        builder.push(" LIMIT ").push_bind(query.limit + 1);
+
+        let regions = builder
+            .build_query_as::<Region>()
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(regions)
+    }
+}
+
+#[cfg(test)]
+#[derive(Default)]
+struct MemoryOrders {
+    orders: BTreeMap<i32, Order>,
+    regions: BTreeMap<i32, Region>,
+}
+
+#[cfg(test)]
+impl MemoryOrders {
+    /// The ids of the region and all of its descendants.
+    fn subtree(&self, root: i32) -> HashSet<i32> {
+        let mut ids = HashSet::from([root]);
+        let mut grown = true;
+        while grown {
+            grown = false;
+            for region in self.regions.values() {
+                if region.parent_id.is_some_and(|parent_id| ids.contains(&parent_id)) && ids.insert(region.id) {
+                    grown = true;
+                }
+            }
+        }
+        ids
+    }
+
+    fn region_name(&self, id: i32) -> String {
+        self.regions.get(&id).and_then(|region| region.name.clone()).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryOrderRepository {
+    state: Mutex<MemoryOrders>,
+}
+
+#[cfg(test)]
+fn gifts_by_popularity<'a>(orders: impl Iterator<Item = &'a Order>) -> Vec<GiftTotal> {
+    let mut totals: HashMap<&str, i64> = HashMap::new();
+    for order in orders {
+        if let Some(gift_name) = &order.gift_name {
+            *totals.entry(gift_name).or_default() += order.quantity as i64;
+        }
+    }
+
+    let mut gifts: Vec<GiftTotal> = totals
+        .into_iter()
+        .map(|(gift_name, quantity)| GiftTotal { gift_name: gift_name.to_string(), quantity })
+        .collect();
+    gifts.sort_by(|a, b| b.quantity.cmp(&a.quantity).then(a.gift_name.cmp(&b.gift_name)));
+    gifts
+}
+
+/// Whether the gift name starts with `prefix`; like `starts_with` in Postgres, never for orders
+/// without one.
+#[cfg(test)]
+fn has_prefix(order: &Order, prefix: &str) -> bool {
+    order.gift_name.as_deref().is_some_and(|gift_name| gift_name.starts_with(prefix))
+}
+
+#[cfg(test)]
+#[async_trait]
+impl OrderRepository for MemoryOrderRepository {
+    async fn reset(&self) -> Result<(), AppError> {
+        *self.state.lock().await = MemoryOrders::default();
+        Ok(())
+    }
+
+    async fn insert_orders(
+        &self,
+        orders: &[Order],
+        on_conflict: OnConflict,
+        missing_regions: MissingRegions,
+    ) -> Result<InsertReport, AppError> {
+        let mut state = self.state.lock().await;
+        let mut report = InsertReport::default();
+
+        let known_regions: Option<HashSet<i32>> = match missing_regions {
+            MissingRegions::Create => None,
+            MissingRegions::Reject => Some(state.regions.keys().copied().collect()),
+        };
+
+        let mut accepted = validate(orders, known_regions.as_ref(), &mut report);
+        if on_conflict != OnConflict::Upsert {
+            accepted.retain(|&index| {
+                let exists = state.orders.contains_key(&orders[index].id);
+                if exists {
+                    report.conflict(index, orders[index].id, on_conflict);
+                }
+                !exists
+            });
+        }
+        if report.must_roll_back(on_conflict) {
+            return Ok(report);
+        }
+
+        for index in accepted {
+            let order = &orders[index];
+            state.regions.entry(order.region_id).or_insert_with(|| Region {
+                id: order.region_id,
+                name: None,
+                parent_id: None,
+            });
+            match state.orders.insert(order.id, order.clone()) {
+                Some(_) => report.updated += 1,
+                None => report.inserted += 1,
+            }
+        }
+
+        report.committed = true;
+        Ok(report)
+    }
+
+    async fn insert_regions(&self, regions: &[Region]) -> Result<(), AppError> {
+        let mut state = self.state.lock().await;
+
+        check_region_names(regions)?;
+        check_region_ids(regions, &state.regions.keys().copied().collect())?;
+
+        for region in regions {
+            state.regions.insert(region.id, region.clone());
+        }
+        Ok(())
+    }
+
+    async fn total_quantity(&self) -> Result<i64, AppError> {
+        Ok(self.state.lock().await.orders.values().map(|order| order.quantity as i64).sum())
+    }
+
+    async fn most_popular_gift(&self) -> Result<Option<String>, AppError> {
+        Ok(gifts_by_popularity(self.state.lock().await.orders.values()).into_iter().next().map(|gift| gift.gift_name))
+    }
+
+    async fn region_totals(&self, rollup: bool) -> Result<Vec<RegionTotal>, AppError> {
+        let state = self.state.lock().await;
+
+        if rollup {
+            let mut totals: Vec<(String, i32, i64)> = state.regions
+                .values()
+                .filter(|region| region.name.is_some())
+                .filter_map(|&Region { id, .. }| {
+                    let subtree = state.subtree(id);
+                    let mut orders = state.orders.values().filter(|order| subtree.contains(&order.region_id)).peekable();
+                    orders.peek()?;
+                    Some((state.region_name(id), id, orders.map(|order| order.quantity as i64).sum()))
+                })
+                .collect();
+            totals.sort();
+
+            return Ok(totals.into_iter().map(|(region, _, total)| RegionTotal { region, total }).collect());
+        }
+
+        let mut totals: BTreeMap<String, i64> = BTreeMap::new();
+        for order in state.orders.values() {
+            if let Some(name) = state.regions.get(&order.region_id).and_then(|region| region.name.clone()) {
+                *totals.entry(name).or_default() += order.quantity as i64;
+            }
+        }
+
+        Ok(totals.into_iter().map(|(region, total)| RegionTotal { region, total }).collect())
+    }
+
+    async fn top_gifts(&self, query: &TopListQuery) -> Result<Vec<RegionTopList<GiftTotal>>, AppError> {
+        let state = self.state.lock().await;
+
+        let mut regions_top_list: Vec<RegionTopList<GiftTotal>> = state.regions
+            .values()
+            .filter(|region| region.name.is_some())
+            .map(|&Region { id, .. }| {
+                let region_ids = if query.rollup { state.subtree(id) } else { HashSet::from([id]) };
+                let orders = state.orders.values().filter(|order| region_ids.contains(&order.region_id));
+                let mut top_gifts: Vec<GiftTotal> = gifts_by_popularity(orders)
+                    .into_iter()
+                    .filter(|gift| query.min_quantity.is_none_or(|min| gift.quantity >= min))
+                    .collect();
+
+                let mut kept = top_gifts.len().min(query.limit as usize);
+                if query.ties && kept > 0 {
+                    let cut_off = top_gifts[kept - 1].quantity;
+                    kept += top_gifts[kept..].iter().take_while(|gift| gift.quantity == cut_off).count();
+                }
+                top_gifts.truncate(kept);
+
+                RegionTopList { region: state.region_name(id), top_gifts }
+            })
+            .collect();
+        // the regions are in id order, so a stable sort breaks ties by id
+        regions_top_list.sort_by(|a, b| a.region.cmp(&b.region));
+
+        Ok(regions_top_list)
+    }
+
+    async fn region_rollups(&self) -> Result<Vec<RegionRollup>, AppError> {
+        let state = self.state.lock().await;
+
+        Ok(state.regions
+            .values()
+            .filter(|region| region.name.is_some())
+            .map(|region| {
+                let subtree = state.subtree(region.id);
+                RegionRollup {
+                    id: region.id,
+                    name: region.name.clone(),
+                    parent_id: region.parent_id,
+                    total: state.orders
+                        .values()
+                        .filter(|order| subtree.contains(&order.region_id))
+                        .map(|order| order.quantity as i64)
+                        .sum(),
+                }
+            })
+            .collect())
+    }
+
+    async fn analytics(&self, query: &AnalyticsQuery) -> Result<OrderAnalytics, AppError> {
+        let state = self.state.lock().await;
+
+        let mut totals: BTreeMap<(Option<String>, Option<i32>), i64> = BTreeMap::new();
+        for order in state.orders.values() {
+            let matches = query.region_ids.as_ref().is_none_or(|ids| ids.contains(&order.region_id))
+                && query.gift_prefix.as_ref().is_none_or(|prefix| has_prefix(order, prefix))
+                && query.min_quantity.is_none_or(|min| order.quantity >= min);
+            if matches {
+                let key = (
+                    order.gift_name.clone().filter(|_| query.by_gift),
+                    query.by_region.then_some(order.region_id),
+                );
+                *totals.entry(key).or_default() += order.quantity as i64;
+            }
+        }
+
+        let total = totals.values().sum();
+        let mut groups: Vec<GroupTotal> = totals
+            .into_iter()
+            .map(|((gift_name, region_id), quantity)| GroupTotal {
+                region: region_id.and_then(|id| state.regions.get(&id).and_then(|region| region.name.clone())),
+                gift_name,
+                region_id,
+                quantity,
+            })
+            .collect();
+        // the map is already ordered by gift name and region id, so a stable sort keeps the ties in order
+        groups.sort_by_key(|group| std::cmp::Reverse(group.quantity));
+        groups.truncate(query.top as usize);
+
+        Ok(OrderAnalytics { total, groups })
+    }
+
+    async fn all_orders(&self) -> Result<RowStream<Order>, AppError> {
+        let orders: Vec<Order> = self.state.lock().await.orders.values().cloned().collect();
+        Ok(Box::pin(futures::stream::iter(orders.into_iter().map(Ok))))
+    }
+
+    async fn all_regions(&self) -> Result<RowStream<Region>, AppError> {
+        let regions: Vec<Region> = self.state.lock().await.regions.values().cloned().collect();
+        Ok(Box::pin(futures::stream::iter(regions.into_iter().map(Ok))))
+    }
+
+    async fn get_order(&self, id: i32) -> Result<Option<Order>, AppError> {
+        Ok(self.state.lock().await.orders.get(&id).cloned())
+    }
+
+    async fn update_order(&self, id: i32, patch: &OrderPatch) -> Result<Option<Order>, AppError> {
+        let mut state = self.state.lock().await;
+
+        if let Some(gift_name) = &patch.gift_name {
+            check_gift_name(gift_name)?;
+        }
+        if let Some(region_id) = patch.region_id.filter(|region_id| !state.regions.contains_key(region_id)) {
+            return Err(unknown_region(region_id));
+        }
+
+        Ok(state.orders.get_mut(&id).map(|order| {
+            if let Some(region_id) = patch.region_id {
+                order.region_id = region_id;
+            }
+            if let Some(gift_name) = &patch.gift_name {
+                order.gift_name = Some(gift_name.clone());
+            }
+            if let Some(quantity) = patch.quantity {
+                order.quantity = quantity;
+            }
+            order.clone()
+        }))
+    }
+
+    async fn delete_order(&self, id: i32) -> Result<bool, AppError> {
+        Ok(self.state.lock().await.orders.remove(&id).is_some())
+    }
+
+    async fn list_orders(&self, query: &OrderListQuery) -> Result<Vec<Order>, AppError> {
+        let state = self.state.lock().await;
+
+        let compare = |a: &Order, b: &Order| {
+            let ordering = match query.sort {
+                OrderSort::Id => Ordering::Equal,
+                OrderSort::Quantity => a.quantity.cmp(&b.quantity),
+                OrderSort::GiftName => a.gift_name.as_deref().unwrap_or_default().cmp(b.gift_name.as_deref().unwrap_or_default()),
+            }
+                .then(a.id.cmp(&b.id));
+            match query.direction {
+                Direction::Asc => ordering,
+                Direction::Desc => ordering.reverse(),
+            }
+        };
+
+        let mut orders: Vec<Order> = state.orders
+            .values()
+            .filter(|order| query.region_id.is_none_or(|region_id| order.region_id == region_id))
+            .filter(|order| query.gift_prefix.as_ref().is_none_or(|prefix| has_prefix(order, prefix)))
+            .filter(|order| query.min_quantity.is_none_or(|min| order.quantity >= min))
+            .filter(|order| query.max_quantity.is_none_or(|max| order.quantity <= max))
+            .filter(|order| query.after.as_ref().is_none_or(|after| compare(order, after) == Ordering::Greater))
+            .cloned()
+            .collect();
+        orders.sort_by(compare);
+        orders.truncate(query.limit as usize + 1);
+
+        Ok(orders)
+    }
+
+    async fn get_region(&self, id: i32) -> Result<Option<Region>, AppError> {
+        Ok(self.state.lock().await.regions.get(&id).cloned())
+    }
+
+    async fn update_region(&self, id: i32, patch: &RegionPatch) -> Result<Option<Region>, AppError> {
+        check_region_name(patch.name.as_deref())?;
+
+        let mut state = self.state.lock().await;
+        if !state.regions.contains_key(&id) {
+            return Ok(None);
+        }
+        if let Some(Some(parent_id)) = patch.parent_id {
+            if !state.regions.contains_key(&parent_id) {
+                return Err(unknown_parent_region(parent_id));
+            }
+            if state.subtree(id).contains(&parent_id) {
+                return Err(region_cycle(id));
+            }
+        }
+
+        let region = state.regions.get_mut(&id).unwrap();
+        if let Some(name) = &patch.name {
+            region.name = Some(name.clone());
+        }
+        if let Some(parent_id) = patch.parent_id {
+            region.parent_id = parent_id;
+        }
+        Ok(Some(region.clone()))
+    }
+
+    async fn delete_region(&self, id: i32) -> Result<bool, AppError> {
+        let mut state = self.state.lock().await;
+        if state.orders.values().any(|order| order.region_id == id) {
+            return Err(region_in_use(id));
+        }
+        if state.regions.values().any(|region| region.parent_id == Some(id)) {
+            return Err(region_has_subregions(id));
+        }
+        Ok(state.regions.remove(&id).is_some())
+    }
+
+    async fn list_regions(&self, query: &RegionListQuery) -> Result<Vec<Region>, AppError> {
+        let state = self.state.lock().await;
+
+        let compare = |a: &Region, b: &Region| {
+            let ordering = match query.sort {
+                RegionSort::Id => Ordering::Equal,
+                RegionSort::Name => a.name.as_deref().unwrap_or_default().cmp(b.name.as_deref().unwrap_or_default()),
+            }
+                .then(a.id.cmp(&b.id));
+            match query.direction {
+                Direction::Asc => ordering,
+                Direction::Desc => ordering.reverse(),
+            }
+        };
+
+        let mut regions: Vec<Region> = state.regions
+            .values()
+            .filter(|region| {
+                query.name_prefix.as_ref().is_none_or(|prefix| {
+                    region.name.as_ref().is_some_and(|name| name.starts_with(prefix.as_str()))
+                })
+            })
+            .filter(|region| query.after.as_ref().is_none_or(|after| compare(region, after) == Ordering::Greater))
+            .cloned()
+            .collect();
+        regions.sort_by(compare);
+        regions.truncate(query.limit as usize + 1);
+
+        Ok(regions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::TryStreamExt;
+    use sqlx::PgPool;
+
+    use super::{
+        Direction, MissingRegions, OnConflict, Order, OrderListQuery, OrderPatch, OrderRepository, OrderSort, PgOrderRepository,
+        Region, RegionListQuery, RegionPatch, RegionSort, TopListQuery,
+    };
+    use crate::error::AppError;
+
+    fn order(id: i32, region_id: i32, gift_name: &str, quantity: i32) -> Order {
+        Order { id, region_id, gift_name: Some(gift_name.to_string()), quantity }
+    }
+
+    fn region(id: i32, name: Option<&str>, parent_id: Option<i32>) -> Region {
+        Region { id, name: name.map(str::to_string), parent_id }
+    }
+
+    /// An order from before gift names were required, which can only be written directly.
+    async fn insert_nameless_order(pool: &PgPool, id: i32, region_id: i32, quantity: i32) {
+        sqlx::query!("INSERT INTO orders (id, region_id, quantity) VALUES ($1, $2, $3)", id, region_id, quantity)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    /// Norway > (Oslo, Bergen), plus apple, named so that byte order and most locales disagree,
+    /// and the placeholder region 5.
+    async fn seed(repository: &PgOrderRepository, pool: &PgPool) {
+        repository
+            .insert_regions(&[
+                region(2, Some("Oslo"), Some(1)),
+                region(1, Some("Norway"), None),
+                region(3, Some("Bergen"), Some(1)),
+                region(4, Some("apple"), None),
+            ])
+            .await
+            .unwrap();
+        repository
+            .insert_orders(
+                &[order(1, 2, "train", 5), order(2, 3, "doll", 3), order(3, 3, "train", 1), order(4, 1, "doll", 3)],
+                OnConflict::Fail,
+                MissingRegions::Reject,
+            )
+            .await
+            .unwrap();
+        insert_nameless_order(pool, 5, 4, 100).await;
+        // a day 13 order, which gets a placeholder region
+        repository.insert_orders(&[order(6, 5, "train", 50)], OnConflict::Fail, MissingRegions::Create).await.unwrap();
+    }
+
+    fn list_query(sort: OrderSort, direction: Direction, after: Option<Order>) -> OrderListQuery {
+        OrderListQuery {
+            sort,
+            direction,
+            after,
+            limit: 2,
+            region_id: None,
+            gift_prefix: None,
+            min_quantity: None,
+            max_quantity: None,
+        }
+    }
+
+    /// Pages through all orders two at a time, continuing after the last order of each page.
+    async fn list_all_orders(repository: &PgOrderRepository, sort: OrderSort, direction: Direction) -> Vec<i32> {
+        let mut ids = Vec::new();
+        let mut after = None;
+        loop {
+            let mut page = repository.list_orders(&list_query(sort, direction, after)).await.unwrap();
+            let more = page.len() > 2;
+            page.truncate(2);
+            ids.extend(page.iter().map(|order| order.id));
+            if !more {
+                return ids;
+            }
+            after = page.pop();
+        }
+    }
+
+    async fn list_all_regions(repository: &PgOrderRepository, direction: Direction) -> Vec<i32> {
+        let mut ids = Vec::new();
+        let mut after = None;
+        loop {
+            let query = RegionListQuery { sort: RegionSort::Name, direction, after, limit: 2, name_prefix: None };
+            let mut page = repository.list_regions(&query).await.unwrap();
+            let more = page.len() > 2;
+            page.truncate(2);
+            ids.extend(page.iter().map(|region| region.id));
+            if !more {
+                return ids;
+            }
+            after = page.pop();
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_insert_orders(pool: PgPool) {
+        let repository = PgOrderRepository::new(pool);
+
+        let report = repository
+            .insert_orders(&[order(1, 7, "doll", 1), order(2, 8, "train", 2)], OnConflict::Fail, MissingRegions::Create)
+            .await
+            .unwrap();
+        assert!(report.committed);
+        assert_eq!(report.inserted, 2);
+        // placeholder regions for orders of day 13
+        assert_eq!(repository.get_region(7).await.unwrap().unwrap().name, None);
+
+        let report = repository
+            .insert_orders(&[order(3, 7, "ball", 3), order(2, 7, "car", 4)], OnConflict::Fail, MissingRegions::Create)
+            .await
+            .unwrap();
+        assert!(!report.committed);
+        assert_eq!((report.inserted, report.rejected), (0, 1));
+        assert!(repository.get_order(3).await.unwrap().is_none());
+
+        let report = repository
+            .insert_orders(&[order(3, 7, "ball", 3), order(2, 7, "car", 4)], OnConflict::Skip, MissingRegions::Create)
+            .await
+            .unwrap();
+        assert!(report.committed);
+        assert_eq!((report.inserted, report.skipped), (1, 1));
+        assert_eq!(repository.get_order(2).await.unwrap().unwrap().gift_name.as_deref(), Some("train"));
+
+        let report = repository
+            .insert_orders(&[order(4, 8, "kite", 5), order(2, 7, "car", 4)], OnConflict::Upsert, MissingRegions::Create)
+            .await
+            .unwrap();
+        assert!(report.committed);
+        assert_eq!((report.inserted, report.updated), (1, 1));
+        assert_eq!(repository.get_order(2).await.unwrap().unwrap().gift_name.as_deref(), Some("car"));
+
+        let report = repository
+            .insert_orders(&[order(5, 9, "yoyo", 1)], OnConflict::Skip, MissingRegions::Reject)
+            .await
+            .unwrap();
+        assert_eq!((report.inserted, report.rejected), (0, 1));
+        assert!(repository.get_region(9).await.unwrap().is_none());
+
+        assert_eq!(repository.total_quantity().await.unwrap(), 1 + 4 + 3 + 5);
+    }
+
+    #[sqlx::test]
+    async fn test_region_totals(pool: PgPool) {
+        let repository = PgOrderRepository::new(pool.clone());
+        seed(&repository, &pool).await;
+
+        let totals: Vec<(String, i64)> = repository
+            .region_totals(false)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|total| (total.region, total.total))
+            .collect();
+        assert_eq!(
+            totals,
+            [("Bergen".to_string(), 4), ("Norway".to_string(), 3), ("Oslo".to_string(), 5), ("apple".to_string(), 100)]
+        );
+
+        let totals: Vec<(String, i64)> = repository
+            .region_totals(true)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|total| (total.region, total.total))
+            .collect();
+        assert_eq!(
+            totals,
+            [("Bergen".to_string(), 4), ("Norway".to_string(), 12), ("Oslo".to_string(), 5), ("apple".to_string(), 100)]
+        );
+
+        let rollups: Vec<(i32, Option<i32>, i64)> = repository
+            .region_rollups()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|rollup| (rollup.id, rollup.parent_id, rollup.total))
+            .collect();
+        // placeholders are left out
+        assert_eq!(rollups, [(1, None, 12), (2, Some(1), 5), (3, Some(1), 4), (4, None, 100)]);
+
+        // the nameless order outsells every gift, but has no name to report
+        assert_eq!(repository.most_popular_gift().await.unwrap().as_deref(), Some("train"));
+    }
+
+    #[sqlx::test]
+    async fn test_top_gifts(pool: PgPool) {
+        let repository = PgOrderRepository::new(pool.clone());
+        seed(&repository, &pool).await;
+
+        let top_gifts = |limit, rollup, ties, min_quantity| {
+            let repository = &repository;
+            async move {
+                repository
+                    .top_gifts(&TopListQuery { limit, rollup, ties, min_quantity })
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|top_list| {
+                        let gifts: Vec<(String, i64)> =
+                            top_list.top_gifts.into_iter().map(|gift| (gift.gift_name, gift.quantity)).collect();
+                        (top_list.region, gifts)
+                    })
+                    .collect::<Vec<_>>()
+            }
+        };
+        let gifts = |gifts: &[(&str, i64)]| -> Vec<(String, i64)> {
+            gifts.iter().map(|&(name, quantity)| (name.to_string(), quantity)).collect()
+        };
+
+        assert_eq!(
+            top_gifts(1, true, false, None).await,
+            [
+                ("Bergen".to_string(), gifts(&[("doll", 3)])),
+                ("Norway".to_string(), gifts(&[("doll", 6)])),
+                ("Oslo".to_string(), gifts(&[("train", 5)])),
+                ("apple".to_string(), gifts(&[])),
+            ]
+        );
+        assert_eq!(top_gifts(1, true, true, None).await[1].1, gifts(&[("doll", 6), ("train", 6)]));
+        assert_eq!(top_gifts(1, false, true, None).await[1].1, gifts(&[("doll", 3)]));
+        assert_eq!(top_gifts(3, false, false, Some(2)).await[0].1, gifts(&[("doll", 3)]));
+        assert_eq!(top_gifts(3, true, false, Some(4)).await[0].1, gifts(&[]));
+    }
+
+    #[sqlx::test]
+    async fn test_keyset_pagination(pool: PgPool) {
+        let repository = PgOrderRepository::new(pool.clone());
+        repository
+            .insert_orders(
+                &[order(1, 1, "apple", 2), order(2, 1, "Zebra", 2), order(3, 1, "apple", 1), order(4, 1, "banana", 2)],
+                OnConflict::Fail,
+                MissingRegions::Create,
+            )
+            .await
+            .unwrap();
+        insert_nameless_order(&pool, 5, 1, 2).await;
+
+        // nameless orders sort as the empty string, and names in byte order
+        assert_eq!(list_all_orders(&repository, OrderSort::GiftName, Direction::Asc).await, [5, 2, 1, 3, 4]);
+        assert_eq!(list_all_orders(&repository, OrderSort::GiftName, Direction::Desc).await, [4, 3, 1, 2, 5]);
+        assert_eq!(list_all_orders(&repository, OrderSort::Quantity, Direction::Asc).await, [3, 1, 2, 4, 5]);
+        assert_eq!(list_all_orders(&repository, OrderSort::Quantity, Direction::Desc).await, [5, 4, 2, 1, 3]);
+        assert_eq!(list_all_orders(&repository, OrderSort::Id, Direction::Desc).await, [5, 4, 3, 2, 1]);
+
+        let mut query = list_query(OrderSort::Id, Direction::Asc, None);
+        query.gift_prefix = Some("a".to_string());
+        let ids: Vec<i32> = repository.list_orders(&query).await.unwrap().iter().map(|order| order.id).collect();
+        assert_eq!(ids, [1, 3]);
+
+        repository
+            .insert_regions(&[region(2, Some("b"), None), region(4, Some("B"), None), region(3, Some("B"), None)])
+            .await
+            .unwrap();
+        // the placeholder region 1 has no name
+        assert_eq!(list_all_regions(&repository, Direction::Asc).await, [1, 3, 4, 2]);
+        assert_eq!(list_all_regions(&repository, Direction::Desc).await, [2, 4, 3, 1]);
+    }
+
+    #[sqlx::test]
+    async fn test_regions(pool: PgPool) {
+        let repository = PgOrderRepository::new(pool.clone());
+        seed(&repository, &pool).await;
+
+        assert!(matches!(
+            repository.insert_regions(&[region(6, Some(&"x".repeat(51)), None)]).await,
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(matches!(repository.insert_regions(&[region(6, None, None)]).await, Err(AppError::BadRequest(_))));
+        let patch = |name: Option<&str>, parent_id| RegionPatch { name: name.map(str::to_string), parent_id };
+        assert!(matches!(repository.update_region(4, &patch(Some(&"x".repeat(51)), None)).await, Err(AppError::BadRequest(_))));
+        let updated = repository.update_region(4, &patch(Some("Apple"), Some(Some(1)))).await.unwrap().unwrap();
+        assert_eq!((updated.name.as_deref(), updated.parent_id), (Some("Apple"), Some(1)));
+        let updated = repository.update_region(4, &patch(None, Some(None))).await.unwrap().unwrap();
+        assert_eq!((updated.name.as_deref(), updated.parent_id), (Some("Apple"), None));
+        assert!(repository.update_region(99, &patch(None, Some(Some(1)))).await.unwrap().is_none());
+        assert!(matches!(repository.update_region(4, &patch(None, Some(Some(99)))).await, Err(AppError::Conflict(_))));
+        // Norway > Oslo, so neither Norway nor Oslo can go under Oslo
+        assert!(matches!(repository.update_region(1, &patch(None, Some(Some(2)))).await, Err(AppError::Conflict(_))));
+        assert!(matches!(repository.update_region(2, &patch(None, Some(Some(2)))).await, Err(AppError::Conflict(_))));
+
+        let message = |result: Result<bool, AppError>| match result {
+            Err(AppError::Conflict(message)) => message,
+            _ => panic!("expected a conflict"),
+        };
+        assert_eq!(message(repository.delete_region(1).await), "region 1 still has orders");
+        repository.delete_order(6).await.unwrap();
+        assert!(repository.delete_region(5).await.unwrap());
+        assert!(!repository.delete_region(5).await.unwrap());
+
+        let patch = OrderPatch { region_id: Some(99), gift_name: None, quantity: None };
+        assert!(matches!(repository.update_order(4, &patch).await, Err(AppError::Conflict(_))));
+        let patch = OrderPatch { region_id: Some(3), gift_name: None, quantity: Some(7) };
+        let updated = repository.update_order(4, &patch).await.unwrap().unwrap();
+        assert_eq!((updated.region_id, updated.gift_name.as_deref(), updated.quantity), (3, Some("doll"), 7));
+
+        assert_eq!(message(repository.delete_region(1).await), "region 1 still has subregions");
+    }
+
+    #[sqlx::test]
+    async fn test_export(pool: PgPool) {
+        let repository = PgOrderRepository::new(pool.clone());
+        seed(&repository, &pool).await;
+
+        let orders: Vec<Order> = repository.all_orders().await.unwrap().try_collect().await.unwrap();
+        let orders: Vec<(i32, Option<String>)> = orders.into_iter().map(|order| (order.id, order.gift_name)).collect();
+        assert_eq!(orders[3..5], [(4, Some("doll".to_string())), (5, None)]);
+
+        let regions: Vec<Region> = repository.all_regions().await.unwrap().try_collect().await.unwrap();
+        let ids: Vec<i32> = regions.iter().map(|region| region.id).collect();
+        assert_eq!(ids, [1, 2, 3, 4, 5]);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn test_legacy_orders(pool: PgPool) {
+        // the schema as it was before regions and quantities were required
+        let mut migrator = sqlx::migrate!();
+        migrator.migrations =
+            migrator.migrations.iter().filter(|migration| migration.version < 20231222000000).cloned().collect();
+        migrator.run(&pool).await.unwrap();
+        sqlx::query("INSERT INTO regions (id, name) VALUES (3, 'Bergen')").execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO orders VALUES (1, NULL, 'doll', NULL), (2, 3, 'train', 4)").execute(&pool).await.unwrap();
+
+        sqlx::migrate!().run(&pool).await.unwrap();
+
+        let repository = PgOrderRepository::new(pool);
+        let orders: Vec<Order> = repository.all_orders().await.unwrap().try_collect().await.unwrap();
+        let orders: Vec<(i32, i32, i32)> = orders.into_iter().map(|order| (order.id, order.region_id, order.quantity)).collect();
+        assert_eq!(orders, [(1, 2, 0), (2, 3, 4)]);
+
+        // the orders that had no region are moved to a placeholder
+        let regions: Vec<Region> = repository.all_regions().await.unwrap().try_collect().await.unwrap();
+        let regions: Vec<(i32, Option<&str>)> = regions.iter().map(|region| (region.id, region.name.as_deref())).collect();
+        assert_eq!(regions, [(2, None), (3, Some("Bergen"))]);
+        assert_eq!(repository.region_totals(false).await.unwrap().len(), 1);
+    }
+}
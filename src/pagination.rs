@@ -0,0 +1,80 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::error::AppError;
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 500;
+
+/// The requested page size, [`DEFAULT_PAGE_SIZE`] when absent.
+pub fn page_size(limit: Option<i64>) -> Result<i64, AppError> {
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(AppError::BadRequest(format!("limit must be between 1 and {}", MAX_PAGE_SIZE)));
+    }
+    Ok(limit)
+}
+
+/// What a cursor holds: the last row of the previous page and the sort order it was taken in,
+/// as the row only tells where to continue in that order.
+#[derive(Serialize, Deserialize)]
+struct Cursor<S, T> {
+    sort: S,
+    after: T,
+}
+
+/// A page of a keyset-paginated listing. `next` is an opaque cursor to pass as `after` to get
+/// the following page, absent on the last page.
+#[derive(Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next: Option<String>,
+}
+
+impl<T: Serialize> Page<T> {
+    /// Builds a page of a listing with a single order from up to `limit + 1` rows, the extra row
+    /// only signalling that there is more.
+    pub fn new(rows: Vec<T>, limit: i64) -> Result<Self, AppError> {
+        Page::sorted(rows, limit, ())
+    }
+
+    /// Builds a page of rows in the given `sort` order, which the cursor remembers.
+    pub fn sorted<S: Serialize>(mut rows: Vec<T>, limit: i64, sort: S) -> Result<Self, AppError> {
+        let more = rows.len() as i64 > limit;
+        rows.truncate(limit as usize);
+
+        let next = match rows.last() {
+            Some(last) if more => {
+                let cursor = serde_json::to_vec(&Cursor { sort, after: last }).map_err(AppError::internal)?;
+                Some(URL_SAFE_NO_PAD.encode(cursor))
+            }
+            _ => None,
+        };
+        Ok(Page { items: rows, next })
+    }
+}
+
+/// Decodes a cursor returned in [`Page::next`] back into the last row of the previous page.
+pub fn decode_cursor<T: DeserializeOwned>(cursor: &str) -> Result<T, AppError> {
+    decode_sorted_cursor(cursor, ())
+}
+
+/// Like [`decode_cursor`], failing when the cursor was issued for another `sort` order.
+pub fn decode_sorted_cursor<T, S>(cursor: &str, sort: S) -> Result<T, AppError>
+where
+    T: DeserializeOwned,
+    S: DeserializeOwned + PartialEq,
+{
+    let decoded: Cursor<S, T> = URL_SAFE_NO_PAD
+        .decode(cursor)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .ok_or_else(|| AppError::BadRequest(format!("invalid cursor: {}", cursor)))?;
+
+    if decoded.sort != sort {
+        return Err(AppError::bad_request("cursor was issued for another sort order"));
+    }
+    Ok(decoded.after)
+}
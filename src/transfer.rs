@@ -0,0 +1,418 @@
+use actix_web::http::header::{self, ContentType};
+use actix_web::web::{Bytes, Payload};
+use actix_web::{HttpRequest, HttpResponse};
+use csv_core::ReadRecordResult;
+use futures::{stream, Stream, StreamExt};
+use serde::de::{self, DeserializeOwned, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::error::AppError;
+
+// Imports are applied as a single batch, so all of their rows are held in memory.
+const MAX_IMPORT_BYTES: usize = 16 * 1024 * 1024;
+
+const NDJSON: &str = "application/x-ndjson";
+const CSV: &str = "text/csv";
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Json,
+    Ndjson,
+    Csv,
+}
+
+impl Format {
+    /// The format of a request body according to its `Content-Type`, JSON when absent.
+    fn of_request(req: &HttpRequest) -> Result<Self, AppError> {
+        let Some(content_type) = req.headers().get(header::CONTENT_TYPE) else {
+            return Ok(Format::Json);
+        };
+        let content_type = content_type.to_str().map_err(AppError::bad_request)?;
+        let essence = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+
+        match essence.as_str() {
+            "application/json" => Ok(Format::Json),
+            NDJSON => Ok(Format::Ndjson),
+            CSV => Ok(Format::Csv),
+            _ => Err(AppError::BadRequest(format!("unsupported content type: {}", content_type))),
+        }
+    }
+
+    fn content_type(&self) -> ContentType {
+        match self {
+            Format::Json => ContentType::json(),
+            Format::Ndjson => ContentType(NDJSON.parse().unwrap()),
+            Format::Csv => ContentType(CSV.parse().unwrap()),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ExportParams {
+    #[serde(default)]
+    pub format: Format,
+}
+
+/// Reads a batch of rows from a JSON array, NDJSON or CSV (with a header line) body, depending
+/// on the request's `Content-Type`. NDJSON and CSV are parsed record by record as they arrive;
+/// JSON bodies are buffered whole before being parsed.
+pub async fn read_rows<T: DeserializeOwned>(req: &HttpRequest, payload: Payload) -> Result<Vec<T>, AppError> {
+    match Format::of_request(req)? {
+        Format::Json => serde_json::from_slice(&read_body(payload).await?).map_err(AppError::bad_request),
+        Format::Ndjson => read_ndjson(payload).await,
+        Format::Csv => read_csv(payload).await,
+    }
+}
+
+/// Calls `f` with every chunk of the body, failing once it gets larger than [`MAX_IMPORT_BYTES`].
+async fn for_each_chunk(
+    mut payload: Payload,
+    mut f: impl FnMut(&[u8]) -> Result<(), AppError>,
+) -> Result<(), AppError> {
+    let mut size = 0;
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(AppError::bad_request)?;
+        size += chunk.len();
+        if size > MAX_IMPORT_BYTES {
+            return Err(AppError::BadRequest(format!("body larger than {} bytes", MAX_IMPORT_BYTES)));
+        }
+        f(&chunk)?;
+    }
+    Ok(())
+}
+
+async fn read_body(payload: Payload) -> Result<Vec<u8>, AppError> {
+    let mut body = Vec::new();
+    for_each_chunk(payload, |chunk| {
+        body.extend_from_slice(chunk);
+        Ok(())
+    })
+        .await?;
+    Ok(body)
+}
+
+async fn read_ndjson<T: DeserializeOwned>(payload: Payload) -> Result<Vec<T>, AppError> {
+    let mut rows = Vec::new();
+    let mut line = Vec::new();
+    let mut line_number = 0;
+
+    let mut parse_line = |line: &[u8]| -> Result<(), AppError> {
+        line_number += 1;
+        if !line.iter().all(u8::is_ascii_whitespace) {
+            let row = serde_json::from_slice(line)
+                .map_err(|err| AppError::BadRequest(format!("line {}: {}", line_number, err)))?;
+            rows.push(row);
+        }
+        Ok(())
+    };
+
+    for_each_chunk(payload, |mut chunk| {
+        while let Some(end) = chunk.iter().position(|&byte| byte == b'\n') {
+            line.extend_from_slice(&chunk[..end]);
+            parse_line(&line)?;
+            line.clear();
+            chunk = &chunk[end + 1..];
+        }
+        line.extend_from_slice(chunk);
+        Ok(())
+    })
+        .await?;
+    parse_line(&line)?;
+
+    Ok(rows)
+}
+
+/// Parses CSV records as the chunks of a body arrive, the first record being the header.
+struct CsvRows<T> {
+    reader: csv_core::Reader,
+    /// The fields of the record being read, and where each of them ends.
+    fields: Vec<u8>,
+    fields_len: usize,
+    ends: Vec<usize>,
+    ends_len: usize,
+    record_number: usize,
+    headers: Option<csv::ByteRecord>,
+    rows: Vec<T>,
+}
+
+impl<T: DeserializeOwned> CsvRows<T> {
+    fn new() -> Self {
+        CsvRows {
+            reader: csv_core::Reader::new(),
+            fields: vec![0; 1024],
+            fields_len: 0,
+            ends: vec![0; 16],
+            ends_len: 0,
+            record_number: 0,
+            headers: None,
+            rows: Vec::new(),
+        }
+    }
+
+    fn feed(&mut self, chunk: &[u8]) -> Result<(), AppError> {
+        // the reader takes empty input for the end of the body
+        if chunk.is_empty() {
+            return Ok(());
+        }
+        self.parse(chunk)
+    }
+
+    fn finish(mut self) -> Result<Vec<T>, AppError> {
+        self.parse(&[])?;
+        Ok(self.rows)
+    }
+
+    fn parse(&mut self, mut input: &[u8]) -> Result<(), AppError> {
+        loop {
+            let (result, nin, nout, nend) = self.reader.read_record(
+                input,
+                &mut self.fields[self.fields_len..],
+                &mut self.ends[self.ends_len..],
+            );
+            input = &input[nin..];
+            self.fields_len += nout;
+            self.ends_len += nend;
+
+            match result {
+                ReadRecordResult::InputEmpty | ReadRecordResult::End => return Ok(()),
+                ReadRecordResult::OutputFull => self.fields.resize(self.fields.len() * 2, 0),
+                ReadRecordResult::OutputEndsFull => self.ends.resize(self.ends.len() * 2, 0),
+                ReadRecordResult::Record => self.parse_record()?,
+            }
+        }
+    }
+
+    fn parse_record(&mut self) -> Result<(), AppError> {
+        self.record_number += 1;
+        let mut record = csv::ByteRecord::new();
+        let mut start = 0;
+        for &end in &self.ends[..self.ends_len] {
+            record.push_field(&self.fields[start..end]);
+            start = end;
+        }
+        self.fields_len = 0;
+        self.ends_len = 0;
+
+        let Some(headers) = &self.headers else {
+            self.headers = Some(record);
+            return Ok(());
+        };
+        if record.len() != headers.len() {
+            return Err(AppError::BadRequest(format!(
+                "record {}: expected {} fields, found {}",
+                self.record_number,
+                headers.len(),
+                record.len()
+            )));
+        }
+        let row = record
+            .deserialize(Some(headers))
+            .map_err(|err| AppError::BadRequest(format!("record {}: {}", self.record_number, err)))?;
+        self.rows.push(row);
+        Ok(())
+    }
+}
+
+async fn read_csv<T: DeserializeOwned>(payload: Payload) -> Result<Vec<T>, AppError> {
+    let mut rows = CsvRows::new();
+    for_each_chunk(payload, |chunk| rows.feed(chunk)).await?;
+    rows.finish()
+}
+
+/// Collects the field names a struct asks for when deserialized, failing everything else.
+struct FieldNames<'a>(&'a mut &'static [&'static str]);
+
+impl<'de> Deserializer<'de> for FieldNames<'_> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("not a struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        *self.0 = fields;
+        Err(de::Error::custom("only the field names are wanted"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier
+        ignored_any
+    }
+}
+
+/// The CSV header for rows of type `T`, from its field names, so that it is there even when
+/// there are no rows.
+fn csv_header<T: DeserializeOwned>() -> Result<Bytes, AppError> {
+    let mut fields: &[&str] = &[];
+    let _ = T::deserialize(FieldNames(&mut fields));
+    if fields.is_empty() {
+        return Ok(Bytes::new());
+    }
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(fields).map_err(AppError::internal)?;
+    Ok(Bytes::from(writer.into_inner().map_err(AppError::internal)?))
+}
+
+fn encode_row<T: Serialize>(format: Format, index: usize, row: &T) -> Result<Bytes, AppError> {
+    let mut bytes = Vec::new();
+    match format {
+        Format::Json => {
+            if index > 0 {
+                bytes.push(b',');
+            }
+            serde_json::to_writer(&mut bytes, row).map_err(AppError::internal)?;
+        }
+        Format::Ndjson => {
+            serde_json::to_writer(&mut bytes, row).map_err(AppError::internal)?;
+            bytes.push(b'\n');
+        }
+        Format::Csv => {
+            let mut writer = csv::WriterBuilder::new().has_headers(false).from_writer(&mut bytes);
+            writer.serialize(row).map_err(AppError::internal)?;
+            writer.flush().map_err(AppError::internal)?;
+        }
+    }
+    Ok(Bytes::from(bytes))
+}
+
+/// Streams rows back one chunk per row as they are read, as a JSON array, NDJSON or CSV with a
+/// header line. An error while reading aborts the response.
+pub fn write_rows<T: Serialize + DeserializeOwned + 'static>(
+    format: Format,
+    rows: impl Stream<Item = Result<T, AppError>> + 'static,
+) -> HttpResponse {
+    let (open, close) = match format {
+        Format::Json => (Ok(Bytes::from_static(b"[")), "]"),
+        Format::Ndjson => (Ok(Bytes::new()), ""),
+        Format::Csv => (csv_header::<T>(), ""),
+    };
+
+    let rows = rows
+        .enumerate()
+        .map(move |(index, row)| row.and_then(|row| encode_row(format, index, &row)).map_err(actix_web::Error::from));
+    let body = stream::once(async move { open.map_err(actix_web::Error::from) })
+        .chain(rows)
+        .chain(stream::once(async move { Ok(Bytes::from_static(close.as_bytes())) }));
+
+    HttpResponse::Ok().content_type(format.content_type()).streaming(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::Pin;
+
+    use actix_web::error::PayloadError;
+    use actix_web::web::Bytes;
+    use actix_web::{body, dev, test, web, App, FromRequest, HttpRequest, HttpResponse};
+    use futures::{stream, Stream};
+    use serde::{Deserialize, Serialize};
+
+    use super::Format;
+    use crate::error::AppError;
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Row {
+        id: i32,
+        name: Option<String>,
+    }
+
+    async fn echo(req: HttpRequest, payload: web::Payload, params: web::Query<super::ExportParams>) -> Result<HttpResponse, AppError> {
+        let rows: Vec<Row> = super::read_rows(&req, payload).await?;
+        Ok(super::write_rows(params.format, stream::iter(rows.into_iter().map(Ok))))
+    }
+
+    #[actix_web::test]
+    async fn test_round_trips() {
+        let app = test::init_service(App::new().route("/echo", web::post().to(echo))).await;
+
+        let cases = [
+            ("application/json", "json", r#"[{"id":1,"name":"North"},{"id":2,"name":null}]"#),
+            ("application/x-ndjson", "ndjson", "{\"id\":1,\"name\":\"North\"}\n{\"id\":2,\"name\":null}\n"),
+            ("text/csv; charset=utf-8", "csv", "id,name\n1,North\n2,\n"),
+        ];
+
+        for (content_type, format, body) in cases {
+            let req = test::TestRequest::post()
+                .uri(&format!("/echo?format={}", format))
+                .insert_header(("content-type", content_type))
+                .set_payload(body)
+                .to_request();
+
+            let resp = test::call_service(&app, req).await;
+
+            assert!(resp.status().is_success());
+
+            let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+            assert_eq!(String::from_utf8(bytes.to_vec()).unwrap(), body);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_ndjson_across_chunks() {
+        let chunks = ["{\"id\":1,\"na", "me\":\"North\"}\n\n{\"id\"", ":2,\"name\":null}"]
+            .map(|chunk| Ok::<_, PayloadError>(Bytes::from_static(chunk.as_bytes())));
+        let (req, _) = test::TestRequest::post()
+            .insert_header(("content-type", "application/x-ndjson"))
+            .to_http_parts();
+        let mut payload = dev::Payload::from(Box::pin(stream::iter(chunks)) as Pin<Box<dyn Stream<Item = _>>>);
+        let payload = web::Payload::from_request(&req, &mut payload).await.unwrap();
+
+        let rows: Vec<Row> = super::read_rows(&req, payload).await.unwrap();
+
+        assert_eq!(rows, [Row { id: 1, name: Some("North".to_string()) }, Row { id: 2, name: None }]);
+    }
+
+    #[actix_web::test]
+    async fn test_csv_across_chunks() {
+        let chunks = ["id,na", "me\n1,\"North\n", "ern\"\n2", ","]
+            .map(|chunk| Ok::<_, PayloadError>(Bytes::from_static(chunk.as_bytes())));
+        let (req, _) = test::TestRequest::post().insert_header(("content-type", "text/csv")).to_http_parts();
+        let mut payload = dev::Payload::from(Box::pin(stream::iter(chunks)) as Pin<Box<dyn Stream<Item = _>>>);
+        let payload = web::Payload::from_request(&req, &mut payload).await.unwrap();
+
+        let rows: Vec<Row> = super::read_rows(&req, payload).await.unwrap();
+
+        assert_eq!(rows, [Row { id: 1, name: Some("North\nern".to_string()) }, Row { id: 2, name: None }]);
+    }
+
+    #[actix_web::test]
+    async fn test_empty_export() {
+        for (format, expected) in [(Format::Json, "[]"), (Format::Ndjson, ""), (Format::Csv, "id,name\n")] {
+            let resp = super::write_rows::<Row>(format, stream::empty());
+
+            let bytes = body::to_bytes(resp.into_body()).await.unwrap();
+            assert_eq!(String::from_utf8(bytes.to_vec()).unwrap(), expected);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_invalid_bodies() {
+        let app = test::init_service(App::new().route("/echo", web::post().to(echo))).await;
+
+        for (content_type, body) in [
+            ("application/x-ndjson", "{\"id\":1}\nnope\n"),
+            ("text/csv", "id,name\nx,North\n"),
+            ("text/csv", "id,name\n1\n"),
+            ("application/xml", "<rows/>"),
+        ] {
+            let req = test::TestRequest::post()
+                .uri("/echo")
+                .insert_header(("content-type", content_type))
+                .set_payload(body)
+                .to_request();
+
+            let resp = test::call_service(&app, req).await;
+
+            assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+        }
+    }
+}